@@ -0,0 +1,25 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use rust_test::input::RecordReader;
+
+// Arbitrary bytes as a partner csv file: every row must either parse or be
+// reported as a parse error, never panic. Nothing is read past a fatal error.
+fuzz_target!(|data: &[u8]| {
+    let reader = RecordReader::from_reader(Box::new(Cursor::new(data.to_vec())));
+    let mut fatal = false;
+
+    for result in reader {
+        assert!(!fatal);
+
+        match result {
+            Ok(record) => assert!(record.line > 0),
+            Err(e) => {
+                assert!(e.line > 0);
+                fatal = e.fatal;
+            }
+        }
+    }
+});
@@ -0,0 +1,27 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use rust_test::input::RecordReader;
+use rust_test::processor::PaymentProcessor;
+
+// Arbitrary bytes as a csv input, the rows that parse are processed. Every
+// transaction is either applied or rejected without a panic, no invariant is
+// ever violated and the ledger agrees with the accounts.
+fuzz_target!(|data: &[u8]| {
+    let mut processor = PaymentProcessor::new();
+
+    processor.set_check_invariants(true);
+
+    let records = RecordReader::from_reader(Box::new(Cursor::new(data.to_vec())));
+
+    for record in records.flatten() {
+        let _ = processor.try_process(record.transaction);
+    }
+
+    let violations = processor.take_violations();
+
+    assert!(violations.is_empty(), "{:?}", violations);
+    assert!(processor.trial_balance().is_balanced());
+});
@@ -0,0 +1,56 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use rust_test::processor::PaymentProcessor;
+use rust_test::transaction::{Transaction, TransactionType};
+
+/// Transaction with client and transaction ids narrowed, so disputes,
+/// resolves and chargebacks find the transactions they refer to. The amount
+/// is in cent parts, negative ones included.
+#[derive(Debug, Arbitrary)]
+struct FuzzTransaction {
+    transaction_type: u8,
+    client_id: u8,
+    tx_id: u8,
+    amount: Option<i64>,
+    timestamp: Option<u64>,
+}
+
+impl FuzzTransaction {
+    fn transaction(&self) -> Transaction {
+        let transaction_type = match self.transaction_type % 5 {
+            0 => TransactionType::Deposit,
+            1 => TransactionType::Withdrawal,
+            2 => TransactionType::Dispute,
+            3 => TransactionType::Resolve,
+            _ => TransactionType::Chargeback,
+        };
+
+        Transaction {
+            transaction_type,
+            client_id: u16::from(self.client_id % 4),
+            tx_id: u32::from(self.tx_id % 16),
+            amount: self.amount,
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+// Any sequence of transactions is either applied or rejected without a
+// panic, no invariant is ever violated and the ledger agrees with the
+// accounts.
+fuzz_target!(|transactions: Vec<FuzzTransaction>| {
+    let mut processor = PaymentProcessor::new();
+
+    processor.set_check_invariants(true);
+
+    for transaction in &transactions {
+        let _ = processor.try_process(transaction.transaction());
+    }
+
+    let violations = processor.take_violations();
+
+    assert!(violations.is_empty(), "{:?}", violations);
+    assert!(processor.trial_balance().is_balanced());
+});
@@ -1,10 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
 use std::fmt;
+use serde::ser::SerializeStruct;
 use serde::{Serialize, Serializer};
 
+use crate::admin::{AdminActionType, AdminTransaction, ReasonCode};
+use crate::config::DisputePolicy;
+use crate::ledger::{JournalEntry, LedgerAccount};
+use crate::limits::{ClientActivity, LimitKind};
+use crate::statement::StatementEntry;
 use crate::transaction::{Transaction, TransactionType};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum OperationError {
     InsufficientBalance(u16, u32),
     InvalidData(u16, u32),
@@ -13,112 +20,335 @@ pub enum OperationError {
     ResolveNotUnderDispute(u16, u32),
     ChargebackNotUnderDispute(u16, u32),
     InvalidTransactionForDispute(u16, u32),
-    InvalidTransactionForChargeback(u16, u32)
+    InvalidTransactionForChargeback(u16, u32),
+    AccountNotActive(u16, u32),
+    InvalidStatusTransition(u16, AdminActionType),
+    AccountNotEmpty(u16),
+    ClientNotFound(u16),
+    LimitExceeded(u16, u32, LimitKind),
+    TimestampOutOfOrder(u16, u32),
+    DisputeWindowExpired(u16, u32),
+    NegativeAmount(u16, u32),
+    BalanceOverflow(u16, u32),
+    BalanceUnderflow(u16, u32),
+    DuplicateTransaction(u16, u32),
 }
 
-#[derive(Debug, Serialize)]
-pub struct Account {
-    #[serde(rename(serialize = "client"))]
-    client_id: u16,
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    Active,
+    Locked,
+    Frozen,
+    Closed,
+}
 
-    #[serde(serialize_with = "cent_part_to_decimal_serialize")]
-    held: u64,
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeStatus {
+    Undisputed,
+    Disputed,
+    Resolved,
+    ChargedBack,
+}
 
-    #[serde(serialize_with = "cent_part_to_decimal_serialize")]
-    total: u64,
+/// A deposit or withdrawal kept for later disputes. `shortfall` is the part
+/// of the disputed amount that was no longer available to hold.
+#[derive(Debug, Clone)]
+pub struct TransactionRecord {
+    pub transaction: Transaction,
+    pub dispute_status: DisputeStatus,
+    pub disputed_at: Option<u64>,
+    pub shortfall: u64,
+}
 
-    locked: bool,
+impl TransactionRecord {
+    fn new(transaction: Transaction) -> TransactionRecord {
+        TransactionRecord {
+            transaction,
+            dispute_status: DisputeStatus::Undisputed,
+            disputed_at: None,
+            shortfall: 0,
+        }
+    }
 
-    #[serde(skip_serializing)]
-    transactions: HashMap<u32, (bool, Transaction)>,
+    /// Amount held for the dispute, zero when it is not under dispute.
+    pub fn held_in_cent_parts(&self) -> u64 {
+        match self.dispute_status {
+            DisputeStatus::Disputed => self
+                .transaction
+                .amount_in_cent_parts()
+                .saturating_sub(self.shortfall),
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Account {
+    client_id: u16,
+    held: u64,
+    total: u64,
+    owed: u64,
+    status: AccountStatus,
+    status_reason: Option<ReasonCode>,
+    transactions: HashMap<u32, TransactionRecord>,
+    fraud_holds: BTreeMap<u32, u64>,
+    activity: ClientActivity,
+    last_timestamp: Option<u64>,
+    history: Vec<StatementEntry>,
 }
 
-fn cent_part_to_decimal_serialize<S>(x: &u64, s: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    s.serialize_f32((*x as f32) / 10000.0)
+impl Serialize for Account {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = s.serialize_struct("Account", 7)?;
+        state.serialize_field("client", &self.client_id)?;
+        state.serialize_field(
+            "available",
+            &format_cent_parts(self.get_available_in_cent_parts()),
+        )?;
+        state.serialize_field("held", &format_cent_parts(self.held))?;
+        state.serialize_field("total", &format_cent_parts(self.total))?;
+        state.serialize_field("owed", &format_cent_parts(self.owed))?;
+        state.serialize_field("locked", &self.is_locked())?;
+        state.serialize_field("status", &self.status)?;
+        state.end()
+    }
 }
 
 impl Account {
-    pub fn handle(&mut self, transaction: Transaction) -> Result<&Account, OperationError> {
+    /// Applies a transaction and returns the journal entry its balances
+    /// changed by, for the caller to post to the ledger.
+    pub fn handle(&mut self, transaction: Transaction) -> Result<JournalEntry, OperationError> {
+        self.handle_with_policy(transaction, &DisputePolicy::default())
+    }
+
+    pub fn handle_with_policy(
+        &mut self,
+        transaction: Transaction,
+        policy: &DisputePolicy,
+    ) -> Result<JournalEntry, OperationError> {
         match transaction.transaction_type {
             TransactionType::Deposit => self.deposit(transaction),
             TransactionType::Withdrawal => self.withdraw(transaction),
-            TransactionType::Dispute => self.dispute(transaction),
+            TransactionType::Dispute => self.dispute(transaction, policy),
             TransactionType::Resolve => self.resolve(transaction),
             TransactionType::Chargeback => self.chargeback(transaction),
         }
     }
 
+    /// Applies an account lifecycle operation and returns the journal entries
+    /// it released funds with, for the caller to post to the ledger.
+    pub fn handle_admin(
+        &mut self,
+        transaction: AdminTransaction,
+    ) -> Result<Vec<JournalEntry>, OperationError> {
+        let next_status = match (transaction.action_type, self.status) {
+            (AdminActionType::ReleaseFunds, _) if !self.fraud_holds.is_empty() => {
+                return self.release_funds();
+            }
+            (AdminActionType::Unlock, AccountStatus::Locked)
+            | (AdminActionType::Unlock, AccountStatus::Frozen)
+            | (AdminActionType::Reopen, AccountStatus::Closed) => AccountStatus::Active,
+            (AdminActionType::Freeze, AccountStatus::Active) => AccountStatus::Frozen,
+            (AdminActionType::Close, AccountStatus::Active) => {
+                if self.total != 0 || self.held != 0 || self.owed != 0 {
+                    return Err(OperationError::AccountNotEmpty(self.client_id));
+                }
+
+                AccountStatus::Closed
+            }
+            (action_type, _) => {
+                return Err(OperationError::InvalidStatusTransition(
+                    self.client_id,
+                    action_type,
+                ))
+            }
+        };
+
+        self.status = next_status;
+        self.status_reason = Some(transaction.reason);
+
+        Ok(Vec::new())
+    }
+
+    /// Moves the amount of the transaction a flagged row refers to from the
+    /// available to the held balance, as much of it as is available. A
+    /// transaction is held once, until released with `ReleaseFunds`.
+    pub(crate) fn hold_funds(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<Option<JournalEntry>, OperationError> {
+        let amount = match self.transactions.get(&transaction.tx_id) {
+            Some(record) if !self.fraud_holds.contains_key(&transaction.tx_id) => record
+                .transaction
+                .amount_in_cent_parts()
+                .min(self.get_available_in_cent_parts()),
+            _ => return Ok(None),
+        };
+
+        if amount == 0 {
+            return Ok(None);
+        }
+
+        let client_id = self.client_id;
+        let entry = self.post(
+            JournalEntry::new(client_id, transaction.tx_id, transaction.transaction_type).transfer(
+                LedgerAccount::ClientAvailable(client_id),
+                LedgerAccount::ClientHeld(client_id),
+                amount as i64,
+            ),
+        )?;
+
+        self.fraud_holds.insert(transaction.tx_id, amount);
+
+        Ok(Some(entry))
+    }
+
+    /// Returns every fraud hold to the available balance, each one as the
+    /// resolve of the transaction it was held for.
+    fn release_funds(&mut self) -> Result<Vec<JournalEntry>, OperationError> {
+        let client_id = self.client_id;
+        let mut entries = Vec::new();
+
+        for (tx_id, amount) in std::mem::take(&mut self.fraud_holds) {
+            entries.push(
+                self.post(
+                    JournalEntry::new(client_id, tx_id, TransactionType::Resolve).transfer(
+                        LedgerAccount::ClientHeld(client_id),
+                        LedgerAccount::ClientAvailable(client_id),
+                        amount as i64,
+                    ),
+                )?,
+            );
+        }
+
+        Ok(entries)
+    }
+
     pub fn new(client_id: u16) -> Account {
         Account {
             client_id,
             held: 0,
             total: 0,
-            locked: false,
+            owed: 0,
+            status: AccountStatus::Active,
+            status_reason: None,
             transactions: HashMap::new(),
+            fraud_holds: BTreeMap::new(),
+            activity: ClientActivity::default(),
+            last_timestamp: None,
+            history: Vec::new(),
         }
     }
 
-    fn deposit(&mut self, transaction: Transaction) -> Result<&Account, OperationError> {
-        match transaction.amount {
-            Some(amount) => {
-                self.total += get_amount_in_cent_parts(amount);
+    fn deposit(&mut self, transaction: Transaction) -> Result<JournalEntry, OperationError> {
+        self.ensure_active(&transaction)?;
 
-                self.transactions
-                    .insert(transaction.tx_id, (false, transaction));
+        self.ensure_new(&transaction)?;
 
-                Ok(self)
-            }
-            None => Err(OperationError::InvalidData(
-                transaction.client_id,
-                transaction.tx_id,
-            )),
-        }
-    }
+        let amount_to_deposit = checked_amount(&transaction)?;
+        let client_id = self.client_id;
 
-    fn withdraw(&mut self, transaction: Transaction) -> Result<&Account, OperationError> {
-        match transaction.amount {
-            Some(amount) => {
-                let amount_to_withdraw = get_amount_in_cent_parts(amount);
+        let entry = self.post(
+            JournalEntry::new(client_id, transaction.tx_id, TransactionType::Deposit).transfer(
+                LedgerAccount::Settlement,
+                LedgerAccount::ClientAvailable(client_id),
+                amount_to_deposit,
+            ),
+        )?;
 
-                if self.total < amount_to_withdraw {
-                    return Err(OperationError::InsufficientBalance(
-                        transaction.client_id,
-                        transaction.tx_id,
-                    ));
-                }
+        self.transactions
+            .insert(transaction.tx_id, TransactionRecord::new(transaction));
 
-                self.total -= amount_to_withdraw;
+        Ok(entry)
+    }
 
-                self.transactions
-                    .insert(transaction.tx_id, (false, transaction));
+    fn withdraw(&mut self, transaction: Transaction) -> Result<JournalEntry, OperationError> {
+        self.ensure_active(&transaction)?;
 
-                Ok(self)
-            }
-            None => Err(OperationError::InvalidData(
+        self.ensure_new(&transaction)?;
+
+        let amount_to_withdraw = checked_amount(&transaction)?;
+        let client_id = self.client_id;
+
+        if self.get_available_in_cent_parts() < amount_to_withdraw as u64 {
+            return Err(OperationError::InsufficientBalance(
                 transaction.client_id,
                 transaction.tx_id,
-            )),
+            ));
         }
+
+        let entry = self.post(
+            JournalEntry::new(client_id, transaction.tx_id, TransactionType::Withdrawal).transfer(
+                LedgerAccount::ClientAvailable(client_id),
+                LedgerAccount::Settlement,
+                amount_to_withdraw,
+            ),
+        )?;
+
+        self.transactions
+            .insert(transaction.tx_id, TransactionRecord::new(transaction));
+
+        Ok(entry)
     }
 
-    fn dispute(&mut self, transaction: Transaction) -> Result<&Account, OperationError> {
-        match self.transactions.get_mut(&transaction.tx_id) {
-            Some((under_dispute, transaction)) => {
-                if *under_dispute {
-                    return Err(OperationError::DisputeAlreadyUnderDispute(
-                        transaction.client_id,
-                        transaction.tx_id,
-                    ));
+    fn dispute(
+        &mut self,
+        transaction: Transaction,
+        policy: &DisputePolicy,
+    ) -> Result<JournalEntry, OperationError> {
+        let disputed_at = transaction.timestamp;
+        let client_id = self.client_id;
+        let shortfall;
+
+        let entry = match self.transactions.get(&transaction.tx_id) {
+            Some(record) => {
+                let transaction = &record.transaction;
+
+                match record.dispute_status {
+                    DisputeStatus::Disputed => {
+                        return Err(OperationError::DisputeAlreadyUnderDispute(
+                            transaction.client_id,
+                            transaction.tx_id,
+                        ))
+                    }
+                    DisputeStatus::ChargedBack => {
+                        return Err(OperationError::InvalidTransactionForDispute(
+                            transaction.client_id,
+                            transaction.tx_id,
+                        ))
+                    }
+                    DisputeStatus::Undisputed | DisputeStatus::Resolved => {}
                 }
 
-                *under_dispute = true;
-
-                let amount_to_dispute = get_amount_in_cent_parts(transaction.amount.unwrap());
+                if let (Some(window), Some(created_at), Some(disputed_at)) =
+                    (policy.window_seconds, transaction.timestamp, disputed_at)
+                {
+                    if disputed_at > created_at.saturating_add(window) {
+                        return Err(OperationError::DisputeWindowExpired(
+                            transaction.client_id,
+                            transaction.tx_id,
+                        ));
+                    }
+                }
 
-                self.held += amount_to_dispute;
+                let amount_to_dispute = checked_amount(transaction)?;
+                // Funds already withdrawn cannot be held, what is missing is
+                // kept as the shortfall of the dispute.
+                let amount_to_hold = amount_to_dispute.min(
+                    i64::try_from(self.get_available_in_cent_parts()).unwrap_or(i64::MAX),
+                );
+                shortfall = (amount_to_dispute - amount_to_hold) as u64;
+
+                JournalEntry::new(client_id, transaction.tx_id, TransactionType::Dispute).transfer(
+                    LedgerAccount::ClientAvailable(client_id),
+                    LedgerAccount::ClientHeld(client_id),
+                    amount_to_hold,
+                )
             }
             None => {
                 return Err(OperationError::TransactionNotFound(
@@ -126,35 +356,56 @@ impl Account {
                     transaction.tx_id,
                 ))
             }
+        };
+
+        let entry = self.post(entry)?;
+
+        if let Some(record) = self.transactions.get_mut(&transaction.tx_id) {
+            record.dispute_status = DisputeStatus::Disputed;
+            record.disputed_at = disputed_at;
+            record.shortfall = shortfall;
         }
 
-        Ok(self)
+        Ok(entry)
     }
 
-    fn resolve(&mut self, transaction: Transaction) -> Result<&Account, OperationError> {
-        match self.transactions.get_mut(&transaction.tx_id) {
-            Some((under_dispute, transaction)) => {
-                if !*under_dispute {
+    fn resolve(&mut self, transaction: Transaction) -> Result<JournalEntry, OperationError> {
+        let client_id = self.client_id;
+
+        let entry = match self.transactions.get(&transaction.tx_id) {
+            Some(record) => {
+                let transaction = &record.transaction;
+
+                if record.dispute_status != DisputeStatus::Disputed {
                     return Err(OperationError::ResolveNotUnderDispute(
                         transaction.client_id,
                         transaction.tx_id,
                     ));
                 }
 
+                let amount_to_resolve = checked_amount(transaction)?;
+                let entry =
+                    JournalEntry::new(client_id, transaction.tx_id, TransactionType::Resolve)
+                        .transfer(
+                            LedgerAccount::ClientHeld(client_id),
+                            LedgerAccount::ClientAvailable(client_id),
+                            record.held_in_cent_parts() as i64,
+                        );
+
                 match transaction.transaction_type {
-                    TransactionType::Deposit => {                        
-                        self.held -= get_amount_in_cent_parts(transaction.amount.unwrap());
-                    },
-                    TransactionType::Withdrawal => {
-                        let amount_to_resolve = get_amount_in_cent_parts(transaction.amount.unwrap());
-                        
-                        self.held -= amount_to_resolve;
-                        self.total += amount_to_resolve;
+                    TransactionType::Deposit => entry,
+                    TransactionType::Withdrawal => entry.transfer(
+                        LedgerAccount::Settlement,
+                        LedgerAccount::ClientAvailable(client_id),
+                        amount_to_resolve,
+                    ),
+                    _ => {
+                        return Err(OperationError::InvalidTransactionForDispute(
+                            transaction.client_id,
+                            transaction.tx_id,
+                        ))
                     }
-                    _ => return Err(OperationError::InvalidTransactionForDispute(transaction.client_id, transaction.tx_id))
                 }
-
-                *under_dispute = false;                
             }
             None => {
                 return Err(OperationError::TransactionNotFound(
@@ -162,15 +413,27 @@ impl Account {
                     transaction.tx_id,
                 ))
             }
+        };
+
+        let entry = self.post(entry)?;
+
+        if let Some(record) = self.transactions.get_mut(&transaction.tx_id) {
+            record.dispute_status = DisputeStatus::Resolved;
+            record.disputed_at = None;
+            record.shortfall = 0;
         }
 
-        Ok(self)
+        Ok(entry)
     }
 
-    fn chargeback(&mut self, transaction: Transaction) -> Result<&Account, OperationError> {
-        match self.transactions.get(&transaction.tx_id) {
-            Some((under_dispute, transaction)) => {
-                if !*under_dispute {
+    fn chargeback(&mut self, transaction: Transaction) -> Result<JournalEntry, OperationError> {
+        let client_id = self.client_id;
+
+        let entry = match self.transactions.get(&transaction.tx_id) {
+            Some(record) => {
+                let transaction = &record.transaction;
+
+                if record.dispute_status != DisputeStatus::Disputed {
                     return Err(OperationError::ChargebackNotUnderDispute(
                         transaction.client_id,
                         transaction.tx_id,
@@ -179,14 +442,33 @@ impl Account {
 
                 match transaction.transaction_type {
                     TransactionType::Deposit => {
-                        let amount_to_chargeback =
-                            get_amount_in_cent_parts(transaction.amount.unwrap());
-
-                        self.held -= amount_to_chargeback;
-                        self.total -= amount_to_chargeback;
-                        self.locked = true;
+                        checked_amount(transaction)?;
+
+                        let entry =
+                            JournalEntry::new(client_id, transaction.tx_id, TransactionType::Chargeback)
+                                .transfer(
+                                    LedgerAccount::ClientHeld(client_id),
+                                    LedgerAccount::ChargebackLoss,
+                                    record.held_in_cent_parts() as i64,
+                                );
+
+                        // The shortfall is returned as well, the client owes it.
+                        if record.shortfall > 0 {
+                            entry.transfer(
+                                LedgerAccount::ClientReceivable(client_id),
+                                LedgerAccount::ChargebackLoss,
+                                record.shortfall as i64,
+                            )
+                        } else {
+                            entry
+                        }
+                    }
+                    _ => {
+                        return Err(OperationError::InvalidTransactionForChargeback(
+                            transaction.client_id,
+                            transaction.tx_id,
+                        ))
                     }
-                    _ => return Err(OperationError::InvalidTransactionForChargeback(transaction.client_id, transaction.tx_id))
                 }
             }
             None => {
@@ -195,9 +477,130 @@ impl Account {
                     transaction.tx_id,
                 ))
             }
+        };
+
+        let entry = self.post(entry)?;
+        self.status = AccountStatus::Locked;
+
+        if let Some(record) = self.transactions.get_mut(&transaction.tx_id) {
+            record.dispute_status = DisputeStatus::ChargedBack;
+            record.disputed_at = None;
         }
 
-        Ok(self)
+        Ok(entry)
+    }
+
+    /// Applies the client side of a journal entry and hands the entry back.
+    /// Balances change only here, so they always match the postings of the
+    /// entries returned. Nothing is changed when a balance would leave the
+    /// range of `u64`.
+    fn post(&mut self, entry: JournalEntry) -> Result<JournalEntry, OperationError> {
+        let held = entry.change_of(LedgerAccount::ClientHeld(self.client_id));
+        let available = entry.change_of(LedgerAccount::ClientAvailable(self.client_id));
+        let receivable = entry.change_of(LedgerAccount::ClientReceivable(self.client_id));
+
+        let next_held = checked_change(self.held, held, &entry)?;
+        let next_total = checked_change(self.total, available + held, &entry)?;
+        let next_owed = checked_change(self.owed, -receivable, &entry)?;
+
+        self.held = next_held;
+        self.total = next_total;
+        self.owed = next_owed;
+
+        Ok(entry)
+    }
+
+    fn ensure_active(&self, transaction: &Transaction) -> Result<(), OperationError> {
+        if self.status != AccountStatus::Active {
+            return Err(OperationError::AccountNotActive(
+                transaction.client_id,
+                transaction.tx_id,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Transaction ids are unique within an account, a stored deposit or
+    /// withdrawal is never replaced.
+    fn ensure_new(&self, transaction: &Transaction) -> Result<(), OperationError> {
+        if self.transactions.contains_key(&transaction.tx_id) {
+            return Err(OperationError::DuplicateTransaction(
+                transaction.client_id,
+                transaction.tx_id,
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn get_transaction(&self, tx_id: u32) -> Option<&TransactionRecord> {
+        self.transactions.get(&tx_id)
+    }
+
+    /// Deposits and withdrawals kept for disputes, in no particular order.
+    pub fn get_transactions(&self) -> impl Iterator<Item = &TransactionRecord> {
+        self.transactions.values()
+    }
+
+    pub fn get_last_timestamp(&self) -> Option<u64> {
+        self.last_timestamp
+    }
+
+    pub(crate) fn set_last_timestamp(&mut self, timestamp: u64) {
+        self.last_timestamp = Some(
+            self.last_timestamp
+                .map_or(timestamp, |last| last.max(timestamp)),
+        );
+    }
+
+    /// Accepted movements in processing order, recorded by the processor.
+    pub fn get_history(&self) -> &[StatementEntry] {
+        &self.history
+    }
+
+    pub(crate) fn push_history(&mut self, entry: StatementEntry) {
+        self.history.push(entry);
+    }
+
+    pub fn get_activity(&self) -> &ClientActivity {
+        &self.activity
+    }
+
+    pub(crate) fn get_activity_mut(&mut self) -> &mut ClientActivity {
+        &mut self.activity
+    }
+
+    pub(crate) fn set_status(&mut self, status: AccountStatus, reason: ReasonCode) {
+        self.status = status;
+        self.status_reason = Some(reason);
+    }
+
+    pub fn get_total_in_cent_parts(&self) -> u64 {
+        self.total
+    }
+
+    pub fn get_held_in_cent_parts(&self) -> u64 {
+        self.held
+    }
+
+    pub fn get_available_in_cent_parts(&self) -> u64 {
+        self.total.saturating_sub(self.held)
+    }
+
+    /// Funds held by the `hold_funds` fraud action, not by disputes.
+    pub fn get_fraud_held_in_cent_parts(&self) -> u64 {
+        self.fraud_holds.values().sum()
+    }
+
+    /// Shortfalls of charged back deposits, funds the client withdrew before
+    /// they were returned and now owes.
+    pub fn get_owed_in_cent_parts(&self) -> u64 {
+        self.owed
+    }
+
+    pub fn get_client_id(&self) -> u16 {
+        self.client_id
     }
 
     pub fn get_total(&self) -> f32 {
@@ -209,15 +612,19 @@ impl Account {
     }
 
     pub fn get_available(&self) -> f32 {
-        if self.total < self.held {
-            return 0.0;
-        }
-
-        get_amount_as_decimal(self.total)
+        get_amount_as_decimal(self.get_available_in_cent_parts())
     }
 
     pub fn is_locked(&self) -> bool {
-        self.locked
+        self.status == AccountStatus::Locked
+    }
+
+    pub fn get_status(&self) -> AccountStatus {
+        self.status
+    }
+
+    pub fn get_status_reason(&self) -> Option<ReasonCode> {
+        self.status_reason
     }
 }
 
@@ -225,16 +632,67 @@ impl fmt::Display for Account {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "{},{},{},{},{}",
+            "{},{},{},{},{},{},{}",
             self.client_id,
-            self.get_available(),
-            self.get_held(),
-            self.get_total(),
+            format_cent_parts(self.get_available_in_cent_parts()),
+            format_cent_parts(self.held),
+            format_cent_parts(self.total),
+            format_cent_parts(self.owed),
             self.is_locked(),
+            self.status,
         )
     }
 }
 
+impl fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AccountStatus::Active => write!(f, "active"),
+            AccountStatus::Locked => write!(f, "locked"),
+            AccountStatus::Frozen => write!(f, "frozen"),
+            AccountStatus::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+impl fmt::Display for DisputeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DisputeStatus::Undisputed => write!(f, "undisputed"),
+            DisputeStatus::Disputed => write!(f, "disputed"),
+            DisputeStatus::Resolved => write!(f, "resolved"),
+            DisputeStatus::ChargedBack => write!(f, "charged_back"),
+        }
+    }
+}
+
+impl OperationError {
+    /// Stable machine readable name of the error, used in reports.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            OperationError::InsufficientBalance(..) => "insufficient_balance",
+            OperationError::InvalidData(..) => "invalid_data",
+            OperationError::TransactionNotFound(..) => "transaction_not_found",
+            OperationError::DisputeAlreadyUnderDispute(..) => "already_disputed",
+            OperationError::ResolveNotUnderDispute(..) => "resolve_not_disputed",
+            OperationError::ChargebackNotUnderDispute(..) => "chargeback_not_disputed",
+            OperationError::InvalidTransactionForDispute(..) => "invalid_dispute",
+            OperationError::InvalidTransactionForChargeback(..) => "invalid_chargeback",
+            OperationError::AccountNotActive(..) => "account_not_active",
+            OperationError::InvalidStatusTransition(..) => "invalid_status_transition",
+            OperationError::AccountNotEmpty(..) => "account_not_empty",
+            OperationError::ClientNotFound(..) => "client_not_found",
+            OperationError::LimitExceeded(..) => "limit_exceeded",
+            OperationError::TimestampOutOfOrder(..) => "timestamp_out_of_order",
+            OperationError::DisputeWindowExpired(..) => "dispute_window_expired",
+            OperationError::NegativeAmount(..) => "negative_amount",
+            OperationError::BalanceOverflow(..) => "balance_overflow",
+            OperationError::BalanceUnderflow(..) => "balance_underflow",
+            OperationError::DuplicateTransaction(..) => "duplicate_transaction",
+        }
+    }
+}
+
 impl fmt::Display for OperationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -294,12 +752,183 @@ impl fmt::Display for OperationError {
                     client_id, tx_id
                 )
             }
+            OperationError::AccountNotActive(client_id, tx_id) => {
+                write!(
+                    f,
+                    "Client {} Account is not active for transaction {}",
+                    client_id, tx_id
+                )
+            }
+            OperationError::InvalidStatusTransition(client_id, action_type) => {
+                write!(
+                    f,
+                    "Client {} Account status does not allow {:?}",
+                    client_id, action_type
+                )
+            }
+            OperationError::AccountNotEmpty(client_id) => {
+                write!(
+                    f,
+                    "Client {} Account must have zero balance to be closed",
+                    client_id
+                )
+            }
+            OperationError::ClientNotFound(client_id) => {
+                write!(f, "Client {} No account found", client_id)
+            }
+            OperationError::LimitExceeded(client_id, tx_id, kind) => {
+                write!(
+                    f,
+                    "Client {} Transaction {} exceeds {}",
+                    client_id, tx_id, kind
+                )
+            }
+            OperationError::DisputeWindowExpired(client_id, tx_id) => {
+                write!(
+                    f,
+                    "Client {} Dispute window has passed for transaction {}",
+                    client_id, tx_id
+                )
+            }
+            OperationError::TimestampOutOfOrder(client_id, tx_id) => {
+                write!(
+                    f,
+                    "Client {} Transaction {} is older than the reordering window allows",
+                    client_id, tx_id
+                )
+            }
+            OperationError::NegativeAmount(client_id, tx_id) => {
+                write!(
+                    f,
+                    "Client {} Transaction {} has a negative amount",
+                    client_id, tx_id
+                )
+            }
+            OperationError::BalanceOverflow(client_id, tx_id) => {
+                write!(
+                    f,
+                    "Client {} Transaction {} would overflow the balance",
+                    client_id, tx_id
+                )
+            }
+            OperationError::BalanceUnderflow(client_id, tx_id) => {
+                write!(
+                    f,
+                    "Client {} Transaction {} would take the balance below zero",
+                    client_id, tx_id
+                )
+            }
+            OperationError::DuplicateTransaction(client_id, tx_id) => {
+                write!(
+                    f,
+                    "Client {} Transaction {} has already been processed",
+                    client_id, tx_id
+                )
+            }
         }
     }
 }
 
-fn get_amount_in_cent_parts(amount: f32) -> u64 {
-    (amount * 10000.0).round() as u64
+/// Cent parts of the amount of a transaction, refusing amounts that are
+/// missing or negative.
+pub(crate) fn checked_amount(transaction: &Transaction) -> Result<i64, OperationError> {
+    let (client_id, tx_id) = (transaction.client_id, transaction.tx_id);
+
+    match transaction.amount {
+        Some(amount) if amount < 0 => Err(OperationError::NegativeAmount(client_id, tx_id)),
+        Some(amount) => Ok(amount),
+        None => Err(OperationError::InvalidData(client_id, tx_id)),
+    }
+}
+
+/// Adds a signed change to a balance, failing instead of wrapping.
+fn checked_change(balance: u64, change: i128, entry: &JournalEntry) -> Result<u64, OperationError> {
+    let overflow = || OperationError::BalanceOverflow(entry.client_id, entry.tx_id);
+    let next = (balance as i128).checked_add(change).ok_or_else(overflow)?;
+
+    if next < 0 {
+        return Err(OperationError::BalanceUnderflow(
+            entry.client_id,
+            entry.tx_id,
+        ));
+    }
+
+    if next > u64::MAX as i128 {
+        return Err(overflow());
+    }
+
+    Ok(next as u64)
+}
+
+/// Renders cent parts as a decimal with exactly four fractional digits.
+pub fn format_cent_parts(amount: u64) -> String {
+    format!("{}.{:04}", amount / 10000, amount % 10000)
+}
+
+/// Same as `format_cent_parts` for the amount of a transaction as read,
+/// which may be negative.
+pub fn format_amount(amount: i64) -> String {
+    let cent_parts = format_cent_parts(amount.unsigned_abs());
+
+    if amount < 0 {
+        format!("-{}", cent_parts)
+    } else {
+        cent_parts
+    }
+}
+
+/// Same as `format_cent_parts` for a difference of amounts, always signed.
+pub fn format_cent_delta(delta: i128) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    let amount = delta.unsigned_abs();
+
+    format!("{}{}.{:04}", sign, amount / 10000, amount % 10000)
+}
+
+/// Parses a decimal with at most four fractional digits into cent parts
+/// without going through floating point.
+pub fn parse_cent_parts(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let invalid = || format!("invalid amount {}", text);
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+
+    if (whole.is_empty() && fraction.is_empty())
+        || fraction.len() > 4
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| invalid())?
+    };
+    let fraction: u64 = format!("{:0<4}", fraction).parse().map_err(|_| invalid())?;
+
+    whole
+        .checked_mul(10000)
+        .and_then(|whole| whole.checked_add(fraction))
+        .ok_or_else(invalid)
+}
+
+/// Same as `parse_cent_parts` for the amount of a transaction, a leading
+/// minus sign is accepted so that negative amounts can be rejected later.
+pub fn parse_amount(text: &str) -> Result<i64, String> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let cent_parts = parse_cent_parts(digits)
+        .ok()
+        .and_then(|cent_parts| i64::try_from(cent_parts).ok())
+        .ok_or_else(|| format!("invalid amount {}", text))?;
+
+    Ok(if negative { -cent_parts } else { cent_parts })
 }
 
 fn get_amount_as_decimal(amount: u64) -> f32 {
@@ -0,0 +1,28 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdminActionType {
+    Unlock,
+    Freeze,
+    Close,
+    Reopen,
+    /// Returns the funds held by fraud rules to the available balance.
+    ReleaseFunds,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReasonCode {
+    CustomerRequest,
+    FraudInvestigation,
+    ComplianceReview,
+    ChargebackReviewed,
+    Other,
+}
+
+/// Account lifecycle operation. Kept apart from `Transaction` so that it can
+/// only be applied through `PaymentProcessor::process_admin` and never
+/// deserialized from ordinary input.
+#[derive(Debug, Clone)]
+pub struct AdminTransaction {
+    pub action_type: AdminActionType,
+    pub client_id: u16,
+    pub reason: ReasonCode,
+}
@@ -0,0 +1,188 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::account::format_amount;
+use crate::processor::ProcessedTransaction;
+
+/// Previous hash of the first record of a log.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Processed transaction and its outcome as kept in the audit log. The amount
+/// is kept as it was read, negative ones included. `hash` is the SHA-256 of
+/// `prev_hash` and the other fields, `prev_hash` the hash of the record
+/// before it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub sequence: u64,
+    #[serde(rename = "type")]
+    pub transaction_type: String,
+    pub client: u16,
+    pub tx: u32,
+    pub amount: Option<String>,
+    pub timestamp: Option<u64>,
+    pub outcome: String,
+    pub error: Option<String>,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// Appends records to a newline delimited json audit log, chaining each one
+/// to the record before it.
+pub struct AuditLog<W: Write> {
+    writer: W,
+    prev_hash: String,
+}
+
+/// First record of a log that does not belong to the chain. `line` is the
+/// line of the log it was found on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokenLink {
+    pub line: u64,
+    pub sequence: Option<u64>,
+    pub reason: String,
+}
+
+impl AuditRecord {
+    pub fn new(processed: &ProcessedTransaction, prev_hash: &str) -> AuditRecord {
+        let transaction = &processed.transaction;
+        let mut record = AuditRecord {
+            sequence: processed.sequence,
+            transaction_type: transaction.transaction_type.to_string(),
+            client: transaction.client_id,
+            tx: transaction.tx_id,
+            amount: transaction.amount.map(format_amount),
+            timestamp: transaction.timestamp,
+            outcome: if processed.is_accepted() {
+                "accepted"
+            } else {
+                "rejected"
+            }
+            .to_string(),
+            error: processed.error.as_ref().map(|e| e.code().to_string()),
+            prev_hash: prev_hash.to_string(),
+            hash: String::new(),
+        };
+
+        record.hash = record.compute_hash();
+        record
+    }
+
+    /// Hash of the record as it should be, regardless of the stored `hash`.
+    pub fn compute_hash(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let content = format!(
+            "{}|{}|{}|{}|{}|{}|{}|{}|{}",
+            self.prev_hash,
+            self.sequence,
+            self.transaction_type,
+            self.client,
+            self.tx,
+            optional(self.amount.clone()),
+            optional(self.timestamp.map(|timestamp| timestamp.to_string())),
+            self.outcome,
+            optional(self.error.clone())
+        );
+
+        Sha256::digest(content.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+impl<W: Write> AuditLog<W> {
+    pub fn new(writer: W) -> AuditLog<W> {
+        AuditLog::resume(writer, GENESIS_HASH.to_string())
+    }
+
+    /// Continues the chain of an existing log, `prev_hash` is the hash of
+    /// its last record as returned by `last_hash`.
+    pub fn resume(writer: W, prev_hash: String) -> AuditLog<W> {
+        AuditLog { writer, prev_hash }
+    }
+
+    pub fn append(&mut self, processed: &ProcessedTransaction) -> io::Result<AuditRecord> {
+        let record = AuditRecord::new(processed, &self.prev_hash);
+
+        serde_json::to_writer(&mut self.writer, &record)?;
+        writeln!(self.writer)?;
+        self.prev_hash = record.hash.clone();
+
+        Ok(record)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Walks the chain of a log, returning the number of records verified or
+/// the first broken link.
+pub fn verify<R: BufRead>(reader: R) -> Result<u64, BrokenLink> {
+    walk(reader).map(|(records, _)| records)
+}
+
+/// Hash of the last record of a log after verifying the whole chain, the
+/// genesis hash for an empty log.
+pub fn last_hash<R: BufRead>(reader: R) -> Result<String, BrokenLink> {
+    walk(reader).map(|(_, hash)| hash)
+}
+
+fn walk<R: BufRead>(reader: R) -> Result<(u64, String), BrokenLink> {
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut records = 0;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index as u64 + 1;
+        let broken = |sequence, reason: String| BrokenLink {
+            line: line_number,
+            sequence,
+            reason,
+        };
+
+        let line = line.map_err(|e| broken(None, e.to_string()))?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: AuditRecord = serde_json::from_str(&line)
+            .map_err(|e| broken(None, format!("unreadable record: {}", e)))?;
+
+        if record.prev_hash != prev_hash {
+            return Err(broken(
+                Some(record.sequence),
+                "previous hash does not match the record before".to_string(),
+            ));
+        }
+
+        if record.hash != record.compute_hash() {
+            return Err(broken(
+                Some(record.sequence),
+                "hash does not match the record content".to_string(),
+            ));
+        }
+
+        prev_hash = record.hash;
+        records += 1;
+    }
+
+    Ok((records, prev_hash))
+}
+
+impl fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.sequence {
+            Some(sequence) => write!(
+                f,
+                "line {} (sequence {}): {}",
+                self.line, sequence, self.reason
+            ),
+            None => write!(f, "line {}: {}", self.line, self.reason),
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Decimal128Array, RecordBatch, StringArray, UInt16Array, UInt32Array,
+    UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+
+use crate::account::Account;
+use crate::processor::ProcessedTransaction;
+
+/// Amounts are written as exact decimals with the four fractional digits
+/// used internally for cent parts.
+pub const AMOUNT_PRECISION: u8 = 20;
+pub const AMOUNT_SCALE: i8 = 4;
+
+fn amount_field(name: &str, nullable: bool) -> Field {
+    Field::new(
+        name,
+        DataType::Decimal128(AMOUNT_PRECISION, AMOUNT_SCALE),
+        nullable,
+    )
+}
+
+fn amount_array<I: IntoIterator<Item = Option<i128>>>(
+    values: I,
+) -> Result<ArrayRef, Box<dyn Error>> {
+    let array = values
+        .into_iter()
+        .collect::<Decimal128Array>()
+        .with_precision_and_scale(AMOUNT_PRECISION, AMOUNT_SCALE)?;
+
+    Ok(Arc::new(array))
+}
+
+pub fn accounts_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("client", DataType::UInt16, false),
+        amount_field("available", false),
+        amount_field("held", false),
+        amount_field("total", false),
+        amount_field("owed", false),
+        Field::new("locked", DataType::Boolean, false),
+        Field::new("status", DataType::Utf8, false),
+    ])
+}
+
+pub fn ledger_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("sequence", DataType::UInt64, false),
+        Field::new("type", DataType::Utf8, false),
+        Field::new("client", DataType::UInt16, false),
+        Field::new("tx", DataType::UInt32, false),
+        amount_field("amount", true),
+        Field::new("timestamp", DataType::UInt64, true),
+        Field::new("outcome", DataType::Utf8, false),
+        Field::new("error", DataType::Utf8, true),
+    ])
+}
+
+pub fn write_accounts<'a, I>(path: &Path, accounts: I) -> Result<(), Box<dyn Error>>
+where
+    I: IntoIterator<Item = &'a Account>,
+{
+    let accounts: Vec<&Account> = accounts.into_iter().collect();
+    let amounts = |amount: fn(&Account) -> u64| {
+        amount_array(accounts.iter().map(|account| Some(amount(account) as i128)))
+    };
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(
+            accounts
+                .iter()
+                .map(|account| account.get_client_id())
+                .collect::<UInt16Array>(),
+        ),
+        amounts(Account::get_available_in_cent_parts)?,
+        amounts(Account::get_held_in_cent_parts)?,
+        amounts(Account::get_total_in_cent_parts)?,
+        amounts(Account::get_owed_in_cent_parts)?,
+        Arc::new(
+            accounts
+                .iter()
+                .map(|account| Some(account.is_locked()))
+                .collect::<BooleanArray>(),
+        ),
+        Arc::new(
+            accounts
+                .iter()
+                .map(|account| Some(account.get_status().to_string()))
+                .collect::<StringArray>(),
+        ),
+    ];
+
+    write_batch(path, accounts_schema(), columns)
+}
+
+pub fn write_ledger(path: &Path, entries: &[ProcessedTransaction]) -> Result<(), Box<dyn Error>> {
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(
+            entries
+                .iter()
+                .map(|entry| entry.sequence)
+                .collect::<UInt64Array>(),
+        ),
+        Arc::new(
+            entries
+                .iter()
+                .map(|entry| Some(entry.transaction.transaction_type.to_string()))
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            entries
+                .iter()
+                .map(|entry| entry.transaction.client_id)
+                .collect::<UInt16Array>(),
+        ),
+        Arc::new(
+            entries
+                .iter()
+                .map(|entry| entry.transaction.tx_id)
+                .collect::<UInt32Array>(),
+        ),
+        amount_array(
+            entries
+                .iter()
+                .map(|entry| entry.transaction.amount.map(i128::from)),
+        )?,
+        Arc::new(
+            entries
+                .iter()
+                .map(|entry| entry.transaction.timestamp)
+                .collect::<UInt64Array>(),
+        ),
+        Arc::new(
+            entries
+                .iter()
+                .map(|entry| {
+                    Some(if entry.is_accepted() {
+                        "accepted"
+                    } else {
+                        "rejected"
+                    })
+                })
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            entries
+                .iter()
+                .map(|entry| entry.error.as_ref().map(|e| e.to_string()))
+                .collect::<StringArray>(),
+        ),
+    ];
+
+    write_batch(path, ledger_schema(), columns)
+}
+
+fn write_batch(path: &Path, schema: Schema, columns: Vec<ArrayRef>) -> Result<(), Box<dyn Error>> {
+    let schema = Arc::new(schema);
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, None)?;
+
+    writer.write(&batch)?;
+    writer.close()?;
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fraud::FraudConfig;
+use crate::limits::LimitsPolicy;
+
+/// Processing policy, loaded from a toml file. Every section is optional and
+/// falls back to no restrictions.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyConfig {
+    #[serde(default)]
+    pub limits: LimitsPolicy,
+
+    #[serde(default)]
+    pub fraud: FraudConfig,
+
+    #[serde(default)]
+    pub timestamps: TimestampPolicy,
+
+    #[serde(default)]
+    pub disputes: DisputePolicy,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimestampPolicy {
+    /// How far a client's transaction may lag behind the latest timestamp
+    /// already seen for that client. Zero requires non-decreasing timestamps.
+    #[serde(default)]
+    pub reorder_window_seconds: u64,
+}
+
+impl PolicyConfig {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<PolicyConfig, Box<dyn Error>> {
+        PolicyConfig::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(config: &str) -> Result<PolicyConfig, Box<dyn Error>> {
+        Ok(toml::from_str(config)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpiryAction {
+    Resolve,
+    Chargeback,
+}
+
+/// Dispute deadlines are only enforced for transactions carrying timestamps.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisputePolicy {
+    /// How long after the original transaction a dispute may be opened.
+    pub window_seconds: Option<u64>,
+
+    /// How long a dispute may stay open before it is closed automatically.
+    pub expiry_seconds: Option<u64>,
+
+    /// Withdrawals cannot be charged back, so they are always resolved.
+    #[serde(default = "default_expiry_action")]
+    pub on_expiry: ExpiryAction,
+}
+
+fn default_expiry_action() -> ExpiryAction {
+    ExpiryAction::Resolve
+}
+
+impl Default for DisputePolicy {
+    fn default() -> Self {
+        DisputePolicy {
+            window_seconds: None,
+            expiry_seconds: None,
+            on_expiry: default_expiry_action(),
+        }
+    }
+}
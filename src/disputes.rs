@@ -0,0 +1,158 @@
+use std::fmt;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+use crate::account::{format_cent_parts, Account, DisputeStatus};
+use crate::limits::SECONDS_IN_DAY;
+use crate::transaction::TransactionType;
+
+/// Age of an open dispute, `Unknown` when it was opened without a timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AgingBucket {
+    UnderDay,
+    UnderWeek,
+    UnderMonth,
+    OverMonth,
+    Unknown,
+}
+
+pub const AGING_BUCKETS: [AgingBucket; 5] = [
+    AgingBucket::UnderDay,
+    AgingBucket::UnderWeek,
+    AgingBucket::UnderMonth,
+    AgingBucket::OverMonth,
+    AgingBucket::Unknown,
+];
+
+/// Transaction currently under dispute, `amount` is held in cent parts.
+#[derive(Debug, Clone)]
+pub struct OpenDispute {
+    pub client_id: u16,
+    pub tx_id: u32,
+    pub transaction_type: TransactionType,
+    pub amount: u64,
+    pub opened_at: Option<u64>,
+    pub age_seconds: Option<u64>,
+    pub bucket: AgingBucket,
+}
+
+/// Number and held amount of the open disputes of a bucket, `bucket` is
+/// `None` for the totals over all buckets.
+#[derive(Debug, Clone, Copy)]
+pub struct BucketTotal {
+    pub bucket: Option<AgingBucket>,
+    pub count: u64,
+    pub amount: u64,
+}
+
+impl AgingBucket {
+    pub fn from_age(age_seconds: Option<u64>) -> AgingBucket {
+        match age_seconds {
+            None => AgingBucket::Unknown,
+            Some(age) if age < SECONDS_IN_DAY => AgingBucket::UnderDay,
+            Some(age) if age < 7 * SECONDS_IN_DAY => AgingBucket::UnderWeek,
+            Some(age) if age < 30 * SECONDS_IN_DAY => AgingBucket::UnderMonth,
+            Some(_) => AgingBucket::OverMonth,
+        }
+    }
+}
+
+/// Open disputes of the accounts ordered by client and transaction id, aged
+/// relative to `now`.
+pub fn open_disputes<'a, I>(accounts: I, now: u64) -> Vec<OpenDispute>
+where
+    I: IntoIterator<Item = &'a Account>,
+{
+    let mut disputes: Vec<OpenDispute> = accounts
+        .into_iter()
+        .flat_map(|account| account.get_transactions())
+        .filter(|record| record.dispute_status == DisputeStatus::Disputed)
+        .map(|record| {
+            let transaction = &record.transaction;
+            let age_seconds = record.disputed_at.map(|at| now.saturating_sub(at));
+
+            OpenDispute {
+                client_id: transaction.client_id,
+                tx_id: transaction.tx_id,
+                transaction_type: transaction.transaction_type,
+                amount: record.held_in_cent_parts(),
+                opened_at: record.disputed_at,
+                age_seconds,
+                bucket: AgingBucket::from_age(age_seconds),
+            }
+        })
+        .collect();
+
+    disputes.sort_by_key(|dispute| (dispute.client_id, dispute.tx_id));
+    disputes
+}
+
+/// Totals per aging bucket, every bucket included, followed by the totals
+/// over all of them.
+pub fn aging_totals(disputes: &[OpenDispute]) -> Vec<BucketTotal> {
+    AGING_BUCKETS
+        .iter()
+        .map(|bucket| Some(*bucket))
+        .chain(std::iter::once(None))
+        .map(|bucket| {
+            let in_bucket = disputes
+                .iter()
+                .filter(|dispute| bucket.is_none_or(|bucket| dispute.bucket == bucket));
+
+            BucketTotal {
+                bucket,
+                count: in_bucket.clone().count() as u64,
+                amount: in_bucket
+                    .map(|dispute| dispute.amount)
+                    .fold(0, u64::saturating_add),
+            }
+        })
+        .collect()
+}
+
+impl Serialize for OpenDispute {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = s.serialize_struct("OpenDispute", 7)?;
+        state.serialize_field("client", &self.client_id)?;
+        state.serialize_field("tx", &self.tx_id)?;
+        state.serialize_field("type", &self.transaction_type)?;
+        state.serialize_field("amount", &format_cent_parts(self.amount))?;
+        state.serialize_field("opened_at", &self.opened_at)?;
+        state.serialize_field("age_seconds", &self.age_seconds)?;
+        state.serialize_field("bucket", &self.bucket.to_string())?;
+        state.end()
+    }
+}
+
+impl Serialize for BucketTotal {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let bucket = self
+            .bucket
+            .map_or("total".to_string(), |bucket| bucket.to_string());
+
+        let mut state = s.serialize_struct("BucketTotal", 3)?;
+        state.serialize_field("bucket", &bucket)?;
+        state.serialize_field("count", &self.count)?;
+        state.serialize_field("amount", &format_cent_parts(self.amount))?;
+        state.end()
+    }
+}
+
+impl fmt::Display for AgingBucket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AgingBucket::UnderDay => write!(f, "0-1d"),
+            AgingBucket::UnderWeek => write!(f, "1-7d"),
+            AgingBucket::UnderMonth => write!(f, "7-30d"),
+            AgingBucket::OverMonth => write!(f, "30d+"),
+            AgingBucket::Unknown => write!(f, "unknown"),
+        }
+    }
+}
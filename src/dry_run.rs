@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use crate::account::format_cent_delta;
+use crate::processor::ProcessedTransaction;
+use crate::stats::OutcomeCounts;
+
+/// Outcome of running transactions against a copy of a processor.
+#[derive(Debug, Clone, Default)]
+pub struct DryRun {
+    pub outcomes: Vec<ProcessedTransaction>,
+    pub summary: DryRunSummary,
+}
+
+/// Counts by transaction type, rejects by error code and the change of each
+/// client's total balance, in cent parts.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DryRunSummary {
+    pub rows: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub by_type: BTreeMap<String, OutcomeCounts>,
+    pub rejects: BTreeMap<String, u64>,
+
+    #[serde(serialize_with = "serialize_deltas")]
+    pub balance_changes: BTreeMap<u16, i128>,
+}
+
+impl DryRunSummary {
+    pub fn record(&mut self, processed: &ProcessedTransaction) {
+        let counts = self
+            .by_type
+            .entry(processed.transaction.transaction_type.to_string())
+            .or_default();
+
+        self.rows += 1;
+
+        match &processed.error {
+            None => {
+                self.accepted += 1;
+                counts.accepted += 1;
+            }
+            Some(e) => {
+                self.rejected += 1;
+                counts.rejected += 1;
+                *self.rejects.entry(e.code().to_string()).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
+fn serialize_deltas<S>(deltas: &BTreeMap<u16, i128>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.collect_map(
+        deltas
+            .iter()
+            .map(|(client_id, delta)| (client_id, format_cent_delta(*delta))),
+    )
+}
+
+impl fmt::Display for DryRunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "rows: {}", self.rows)?;
+        writeln!(f, "accepted: {}", self.accepted)?;
+        writeln!(f, "rejected: {}", self.rejected)?;
+
+        for (transaction_type, counts) in &self.by_type {
+            writeln!(
+                f,
+                "{}: {} accepted, {} rejected",
+                transaction_type, counts.accepted, counts.rejected
+            )?;
+        }
+
+        for (code, count) in &self.rejects {
+            writeln!(f, "rejected {}: {}", code, count)?;
+        }
+
+        for (client_id, delta) in &self.balance_changes {
+            writeln!(f, "client {}: {}", client_id, format_cent_delta(*delta))?;
+        }
+
+        Ok(())
+    }
+}
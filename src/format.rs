@@ -0,0 +1,118 @@
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+    Ndjson,
+}
+
+impl Format {
+    /// Detects the format from the file extension, looking past a
+    /// compression extension such as `.gz` or `.zst`.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let mut path = path.to_path_buf();
+
+        loop {
+            let extension = path.extension()?.to_str()?.to_lowercase();
+
+            match extension.as_str() {
+                "gz" | "zst" => {
+                    path.set_extension("");
+                }
+                "jsonl" => return Some(Format::Ndjson),
+                other => return other.parse().ok(),
+            }
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Format, String> {
+        match text.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            _ => Err(format!(
+                "unknown format {}, expected csv, json or ndjson",
+                text
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Format::Csv => write!(f, "csv"),
+            Format::Json => write!(f, "json"),
+            Format::Ndjson => write!(f, "ndjson"),
+        }
+    }
+}
+
+/// Writes serializable records as csv rows, a json array or one json
+/// document per line.
+pub struct RecordWriter<W: Write> {
+    target: Target<W>,
+    written: usize,
+}
+
+enum Target<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    Json(W),
+    Ndjson(W),
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(writer: W, format: Format) -> RecordWriter<W> {
+        let target = match format {
+            Format::Csv => Target::Csv(Box::new(csv::Writer::from_writer(writer))),
+            Format::Json => Target::Json(writer),
+            Format::Ndjson => Target::Ndjson(writer),
+        };
+
+        RecordWriter { target, written: 0 }
+    }
+
+    pub fn write<T: Serialize>(&mut self, record: &T) -> Result<(), Box<dyn Error>> {
+        match &mut self.target {
+            Target::Csv(writer) => writer.serialize(record)?,
+            Target::Json(writer) => {
+                writer.write_all(if self.written == 0 { b"[\n" } else { b",\n" })?;
+                serde_json::to_writer(&mut *writer, record)?;
+            }
+            Target::Ndjson(writer) => {
+                serde_json::to_writer(&mut *writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+
+        self.written += 1;
+
+        Ok(())
+    }
+
+    /// Closes the json array and flushes, must be called once all records
+    /// have been written.
+    pub fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        match &mut self.target {
+            Target::Csv(writer) => writer.flush()?,
+            Target::Json(writer) => {
+                writer.write_all(if self.written == 0 { b"[]\n" } else { b"\n]\n" })?;
+                writer.flush()?;
+            }
+            Target::Ndjson(writer) => writer.flush()?,
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::account::Account;
+use crate::transaction::{Transaction, TransactionType};
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FraudAction {
+    Log,
+    HoldFunds,
+    FreezeAccount,
+    LockAccount,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+
+    #[serde(rename = "tx")]
+    pub tx_id: u32,
+
+    pub rule: String,
+    pub action: FraudAction,
+    pub message: String,
+}
+
+/// Data a rule can look at. Rules run after the transaction has been applied,
+/// so the account already reflects it.
+pub struct RuleContext<'a> {
+    pub transaction: &'a Transaction,
+    pub account: &'a Account,
+    pub timestamp: u64,
+}
+
+pub trait FraudRule: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Returns a description of the suspicious pattern if the rule fires.
+    fn evaluate(&self, context: &RuleContext) -> Option<String>;
+
+    /// How far back the rule needs to see client activity.
+    fn window_seconds(&self) -> u64 {
+        0
+    }
+}
+
+/// Withdrawal shortly after a deposit of a comparable amount.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RapidWithdrawalRule {
+    pub window_seconds: u64,
+
+    #[serde(default = "default_min_ratio")]
+    pub min_ratio: f32,
+}
+
+/// Too many disputes opened by a client within the window.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RepeatedDisputesRule {
+    pub max_disputes: u32,
+    pub window_seconds: u64,
+}
+
+/// Share of deposits that ended in a chargeback over the account lifetime.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChargebackRatioRule {
+    pub max_ratio: f32,
+
+    #[serde(default)]
+    pub min_deposits: u64,
+}
+
+fn default_min_ratio() -> f32 {
+    0.9
+}
+
+impl FraudRule for RapidWithdrawalRule {
+    fn name(&self) -> &str {
+        "rapid_withdrawal"
+    }
+
+    fn evaluate(&self, context: &RuleContext) -> Option<String> {
+        let transaction = context.transaction;
+
+        if transaction.transaction_type != TransactionType::Withdrawal {
+            return None;
+        }
+
+        let withdrawn = transaction.amount?.max(0) as f32;
+        let since = context.timestamp.saturating_sub(self.window_seconds);
+
+        context
+            .account
+            .get_activity()
+            .since(since)
+            .filter(|entry| entry.transaction_type == TransactionType::Deposit)
+            .find(|entry| entry.amount > 0 && withdrawn >= entry.amount as f32 * self.min_ratio)
+            .map(|_| {
+                format!(
+                    "Withdrawal within {} seconds of a matching deposit",
+                    self.window_seconds
+                )
+            })
+    }
+
+    fn window_seconds(&self) -> u64 {
+        self.window_seconds
+    }
+}
+
+impl FraudRule for RepeatedDisputesRule {
+    fn name(&self) -> &str {
+        "repeated_disputes"
+    }
+
+    fn evaluate(&self, context: &RuleContext) -> Option<String> {
+        if context.transaction.transaction_type != TransactionType::Dispute {
+            return None;
+        }
+
+        let since = context.timestamp.saturating_sub(self.window_seconds);
+        let disputes = context
+            .account
+            .get_activity()
+            .since(since)
+            .filter(|entry| entry.transaction_type == TransactionType::Dispute)
+            .count();
+
+        if disputes <= self.max_disputes as usize {
+            return None;
+        }
+
+        Some(format!(
+            "{} disputes within {} seconds",
+            disputes, self.window_seconds
+        ))
+    }
+
+    fn window_seconds(&self) -> u64 {
+        self.window_seconds
+    }
+}
+
+impl FraudRule for ChargebackRatioRule {
+    fn name(&self) -> &str {
+        "chargeback_ratio"
+    }
+
+    fn evaluate(&self, context: &RuleContext) -> Option<String> {
+        if context.transaction.transaction_type != TransactionType::Chargeback {
+            return None;
+        }
+
+        let activity = context.account.get_activity();
+        let deposits = activity.lifetime_count(TransactionType::Deposit);
+        let chargebacks = activity.lifetime_count(TransactionType::Chargeback);
+
+        if deposits == 0 || deposits < self.min_deposits {
+            return None;
+        }
+
+        let ratio = chargebacks as f32 / deposits as f32;
+
+        if ratio <= self.max_ratio {
+            return None;
+        }
+
+        Some(format!(
+            "{} of {} deposits charged back",
+            chargebacks, deposits
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FraudConfig {
+    pub rapid_withdrawal: Option<RuleConfig<RapidWithdrawalRule>>,
+    pub repeated_disputes: Option<RuleConfig<RepeatedDisputesRule>>,
+    pub chargeback_ratio: Option<RuleConfig<ChargebackRatioRule>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleConfig<R> {
+    #[serde(flatten)]
+    pub rule: R,
+
+    #[serde(default = "default_action")]
+    pub action: FraudAction,
+}
+
+fn default_action() -> FraudAction {
+    FraudAction::Log
+}
+
+#[derive(Clone, Default)]
+pub struct RuleEngine {
+    rules: Vec<(Arc<dyn FraudRule>, FraudAction)>,
+}
+
+impl RuleEngine {
+    pub fn new() -> RuleEngine {
+        RuleEngine { rules: Vec::new() }
+    }
+
+    pub fn from_config(config: &FraudConfig) -> RuleEngine {
+        let mut engine = RuleEngine::new();
+
+        if let Some(config) = &config.rapid_withdrawal {
+            engine.add_rule(config.rule.clone(), config.action);
+        }
+
+        if let Some(config) = &config.repeated_disputes {
+            engine.add_rule(config.rule.clone(), config.action);
+        }
+
+        if let Some(config) = &config.chargeback_ratio {
+            engine.add_rule(config.rule.clone(), config.action);
+        }
+
+        engine
+    }
+
+    pub fn add_rule<R: FraudRule + 'static>(&mut self, rule: R, action: FraudAction) {
+        self.rules.push((Arc::new(rule), action));
+    }
+
+    pub fn longest_window(&self) -> u64 {
+        self.rules
+            .iter()
+            .map(|(rule, _)| rule.window_seconds())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn evaluate(&self, context: &RuleContext) -> Vec<Alert> {
+        self.rules
+            .iter()
+            .filter_map(|(rule, action)| {
+                rule.evaluate(context).map(|message| Alert {
+                    client_id: context.transaction.client_id,
+                    tx_id: context.transaction.tx_id,
+                    rule: rule.name().to_string(),
+                    action: *action,
+                    message,
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for FraudAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FraudAction::Log => write!(f, "log"),
+            FraudAction::HoldFunds => write!(f, "hold_funds"),
+            FraudAction::FreezeAccount => write!(f, "freeze_account"),
+            FraudAction::LockAccount => write!(f, "lock_account"),
+        }
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Client {} Transaction {} triggered {} ({}): {}",
+            self.client_id, self.tx_id, self.rule, self.action, self.message
+        )
+    }
+}
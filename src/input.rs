@@ -0,0 +1,473 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::iter::Peekable;
+use std::path::Path;
+
+use csv::{ByteRecord, StringRecord};
+use flate2::read::MultiGzDecoder;
+use serde_json::Value;
+
+use crate::format::Format;
+use crate::transaction::Transaction;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+pub type TransactionResult = Result<Transaction, ParseError>;
+pub type RecordResult = Result<InputRecord, ParseError>;
+
+/// Parsed transaction together with where it was read from.
+#[derive(Debug, Clone)]
+pub struct InputRecord {
+    pub source: String,
+    pub line: u64,
+    pub raw: String,
+    pub transaction: Transaction,
+}
+
+/// Input row that could not be turned into a transaction. A `fatal` error
+/// means the rest of the input cannot be read, such as a truncated or
+/// corrupt compressed stream.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub source: String,
+    pub line: u64,
+    pub raw: String,
+    pub message: String,
+    pub fatal: bool,
+}
+
+/// Opens a file, or stdin for `-`, decompressing gzip and zstd content
+/// detected by its magic bytes.
+pub fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    let source: Box<dyn Read> = if path == Path::new("-") {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path)?)
+    };
+
+    let mut reader = BufReader::new(source);
+    let header = reader.fill_buf()?;
+
+    if header.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else if header.starts_with(ZSTD_MAGIC) {
+        Ok(Box::new(zstd::Decoder::with_buffer(reader)?))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Reads input records from csv, newline delimited json or a json array.
+pub struct RecordReader {
+    source: String,
+    records: Box<dyn Iterator<Item = RecordResult>>,
+}
+
+impl RecordReader {
+    /// Picks the format from the file extension, csv when it is not known.
+    pub fn from_path(path: &Path) -> io::Result<RecordReader> {
+        let format = Format::from_path(path).unwrap_or(Format::Csv);
+
+        RecordReader::from_path_with_format(path, format)
+    }
+
+    pub fn from_path_with_format(path: &Path, format: Format) -> io::Result<RecordReader> {
+        Ok(RecordReader::with_format(open(path)?, format).with_source(path.display().to_string()))
+    }
+
+    pub fn from_reader(reader: Box<dyn Read>) -> RecordReader {
+        RecordReader::with_format(reader, Format::Csv)
+    }
+
+    pub fn with_format(reader: Box<dyn Read>, format: Format) -> RecordReader {
+        let records: Box<dyn Iterator<Item = RecordResult>> = match format {
+            Format::Csv => Box::new(CsvRecords::new(reader)),
+            Format::Ndjson => Box::new(NdjsonRecords::new(reader)),
+            Format::Json => Box::new(json_records(reader)),
+        };
+
+        RecordReader {
+            source: String::new(),
+            records,
+        }
+    }
+
+    /// Name reported with every record, the path when read from a file.
+    pub fn with_source(mut self, source: String) -> RecordReader {
+        self.source = source;
+        self
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = RecordResult;
+
+    fn next(&mut self) -> Option<RecordResult> {
+        let mut result = self.records.next()?;
+
+        match &mut result {
+            Ok(record) => record.source.clone_from(&self.source),
+            Err(e) => e.source.clone_from(&self.source),
+        }
+
+        Some(result)
+    }
+}
+
+/// Reads transactions only, dropping where they were read from.
+pub struct TransactionReader {
+    records: RecordReader,
+}
+
+impl TransactionReader {
+    /// Picks the format from the file extension, csv when it is not known.
+    pub fn from_path(path: &Path) -> io::Result<TransactionReader> {
+        Ok(TransactionReader::new(RecordReader::from_path(path)?))
+    }
+
+    pub fn from_path_with_format(path: &Path, format: Format) -> io::Result<TransactionReader> {
+        Ok(TransactionReader::new(RecordReader::from_path_with_format(
+            path, format,
+        )?))
+    }
+
+    pub fn from_reader(reader: Box<dyn Read>) -> TransactionReader {
+        TransactionReader::new(RecordReader::from_reader(reader))
+    }
+
+    pub fn with_format(reader: Box<dyn Read>, format: Format) -> TransactionReader {
+        TransactionReader::new(RecordReader::with_format(reader, format))
+    }
+
+    pub fn new(records: RecordReader) -> TransactionReader {
+        TransactionReader { records }
+    }
+}
+
+impl Iterator for TransactionReader {
+    type Item = TransactionResult;
+
+    fn next(&mut self) -> Option<TransactionResult> {
+        self.records
+            .next()
+            .map(|result| result.map(|record| record.transaction))
+    }
+}
+
+/// Csv rows, tolerating padded fields, short rows, reordered columns and
+/// differently cased headers.
+struct CsvRecords {
+    reader: csv::Reader<Box<dyn Read>>,
+    headers: Option<StringRecord>,
+    record: ByteRecord,
+    done: bool,
+}
+
+impl CsvRecords {
+    fn new(reader: Box<dyn Read>) -> CsvRecords {
+        CsvRecords {
+            reader: csv::ReaderBuilder::new().flexible(true).from_reader(reader),
+            headers: None,
+            record: ByteRecord::new(),
+            done: false,
+        }
+    }
+
+    /// Headers that are not valid UTF-8 cannot be matched to columns, so
+    /// they fail the whole input.
+    fn read_headers(&mut self) -> Result<StringRecord, ParseError> {
+        let headers = self
+            .reader
+            .byte_headers()
+            .map_err(|e| ParseError::from_csv(e, String::new()))?
+            .clone();
+        let line = headers.position().map_or(1, |position| position.line());
+        let raw = lossy_row(&headers);
+        let headers = StringRecord::from_byte_record(headers)
+            .map_err(|e| ParseError::new(line, raw, e.to_string()).fatal())?;
+
+        Ok(headers
+            .iter()
+            .map(|header| header.trim().to_lowercase())
+            .collect())
+    }
+}
+
+impl Iterator for CsvRecords {
+    type Item = RecordResult;
+    fn next(&mut self) -> Option<RecordResult> {
+        if self.done {
+            return None;
+        }
+
+        if self.headers.is_none() {
+            match self.read_headers() {
+                Ok(headers) => self.headers = Some(headers),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => {
+                let error = ParseError::from_csv(e, String::new());
+
+                self.done = error.fatal;
+                return Some(Err(error));
+            }
+        }
+
+        let line = self.record.position().map_or(0, |position| position.line());
+        let raw = lossy_row(&self.record);
+        let mut record = match StringRecord::from_byte_record(self.record.clone()) {
+            Ok(record) => record,
+            Err(e) => return Some(Err(ParseError::new(line, raw, e.to_string()))),
+        };
+
+        record.trim();
+
+        Some(match record.deserialize(self.headers.as_ref()) {
+            Ok(transaction) => Ok(InputRecord::new(line, raw, transaction)),
+            Err(e) => Err(ParseError::new(
+                line,
+                raw,
+                match e.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                    _ => e.to_string(),
+                },
+            )),
+        })
+    }
+}
+
+/// Fields written back as a csv row, quoted where needed so that the row can
+/// be resubmitted as read. Invalid UTF-8 is replaced so the row can still be
+/// reported.
+fn lossy_row(record: &ByteRecord) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    // Writing to memory cannot fail.
+    writer.write_byte_record(record).ok();
+
+    let bytes = writer.into_inner().unwrap_or_default();
+    let row = String::from_utf8_lossy(&bytes);
+
+    row.strip_suffix('\n').unwrap_or(&row).to_string()
+}
+
+/// One json document per line, blank lines are skipped.
+struct NdjsonRecords {
+    lines: io::Lines<BufReader<Box<dyn Read>>>,
+    line: u64,
+    done: bool,
+}
+
+impl NdjsonRecords {
+    fn new(reader: Box<dyn Read>) -> NdjsonRecords {
+        NdjsonRecords {
+            lines: BufReader::new(reader).lines(),
+            line: 0,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for NdjsonRecords {
+    type Item = RecordResult;
+
+    fn next(&mut self) -> Option<RecordResult> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            self.line += 1;
+
+            let raw = match self.lines.next()? {
+                Ok(raw) => raw,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(ParseError::new(
+                        self.line,
+                        String::new(),
+                        e.to_string(),
+                    )
+                    .fatal()));
+                }
+            };
+
+            if raw.trim().is_empty() {
+                continue;
+            }
+
+            return Some(match serde_json::from_str(&raw).and_then(transaction_from_value) {
+                Ok(transaction) => Ok(InputRecord::new(self.line, raw, transaction)),
+                Err(e) => Err(ParseError::new(self.line, raw, e.to_string())),
+            });
+        }
+    }
+}
+
+/// A json array is parsed as a whole, elements are then converted one by one
+/// so that a bad element does not reject its neighbours. The element index
+/// is reported in place of a line number.
+fn json_records(reader: Box<dyn Read>) -> Box<dyn Iterator<Item = RecordResult>> {
+    match serde_json::from_reader::<_, Vec<Value>>(reader) {
+        Ok(values) => Box::new(values.into_iter().enumerate().map(|(index, value)| {
+            let line = index as u64 + 1;
+            let raw = value.to_string();
+
+            match transaction_from_value(value) {
+                Ok(transaction) => Ok(InputRecord::new(line, raw, transaction)),
+                Err(e) => Err(ParseError::new(line, raw, e.to_string())),
+            }
+        })),
+        Err(e) => {
+            let error = ParseError::new(e.line() as u64, String::new(), e.to_string());
+
+            Box::new(std::iter::once(Err(if e.is_io() {
+                error.fatal()
+            } else {
+                error
+            })))
+        }
+    }
+}
+
+/// Json numbers are doubles, a numeric amount is taken as the shortest text
+/// that reads back as the same double and then parsed exactly like csv text.
+/// Amounts given as json strings are parsed as they are.
+fn transaction_from_value(mut value: Value) -> serde_json::Result<Transaction> {
+    if let Some(Value::Number(number)) = value.get("amount") {
+        value["amount"] = Value::String(number.to_string());
+    }
+
+    serde_json::from_value(value)
+}
+
+impl InputRecord {
+    fn new(line: u64, raw: String, transaction: Transaction) -> InputRecord {
+        InputRecord {
+            source: String::new(),
+            line,
+            raw,
+            transaction,
+        }
+    }
+}
+
+impl ParseError {
+    fn new(line: u64, raw: String, message: String) -> ParseError {
+        ParseError {
+            source: String::new(),
+            line,
+            raw,
+            message,
+            fatal: false,
+        }
+    }
+
+    fn fatal(mut self) -> ParseError {
+        self.fatal = true;
+        self
+    }
+
+    fn from_csv(error: csv::Error, raw: String) -> ParseError {
+        let parse_error = ParseError::new(
+            error.position().map_or(0, |position| position.line()),
+            raw,
+            error.to_string(),
+        );
+
+        match error.kind() {
+            csv::ErrorKind::Io(_) => parse_error.fatal(),
+            _ => parse_error,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.fatal {
+            return write!(f, "cannot read {}: {}", self.source, self.message);
+        }
+
+        write!(
+            f,
+            "Line {}: {} in record \"{}\"",
+            self.line, self.message, self.raw
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+/// Reads the inputs one after another, or interleaved by timestamp when
+/// `merge` is set. Each input is expected to be ordered already. Without an
+/// explicit format it is detected per input from the file extension.
+pub fn read_all(
+    paths: &[&Path],
+    format: Option<Format>,
+    merge: bool,
+) -> io::Result<Box<dyn Iterator<Item = RecordResult>>> {
+    let readers = paths
+        .iter()
+        .map(|path| match format {
+            Some(format) => RecordReader::from_path_with_format(path, format),
+            None => RecordReader::from_path(path),
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    if merge {
+        Ok(Box::new(MergeByTimestamp::new(readers)))
+    } else {
+        Ok(Box::new(readers.into_iter().flatten()))
+    }
+}
+
+/// K-way merge of ordered inputs. Rows without a timestamp and rows that
+/// failed to parse are passed through as soon as they reach the front of
+/// their input, ties go to the input listed first.
+pub struct MergeByTimestamp<I: Iterator<Item = RecordResult>> {
+    inputs: Vec<Peekable<I>>,
+}
+
+impl<I: Iterator<Item = RecordResult>> MergeByTimestamp<I> {
+    pub fn new(inputs: Vec<I>) -> MergeByTimestamp<I> {
+        MergeByTimestamp {
+            inputs: inputs.into_iter().map(Iterator::peekable).collect(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = RecordResult>> Iterator for MergeByTimestamp<I> {
+    type Item = RecordResult;
+
+    fn next(&mut self) -> Option<RecordResult> {
+        let mut next: Option<(usize, u64)> = None;
+
+        for (index, input) in self.inputs.iter_mut().enumerate() {
+            let timestamp = match input.peek() {
+                Some(Ok(record)) => record.transaction.timestamp.unwrap_or(0),
+                Some(Err(_)) => 0,
+                None => continue,
+            };
+
+            if next.is_none_or(|(_, earliest)| timestamp < earliest) {
+                next = Some((index, timestamp));
+            }
+        }
+
+        next.and_then(|(index, _)| self.inputs[index].next())
+    }
+}
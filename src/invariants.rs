@@ -0,0 +1,150 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::account::{Account, AccountStatus};
+use crate::ledger::{JournalEntry, LedgerAccount};
+use crate::transaction::{Transaction, TransactionType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Invariant {
+    /// The held amount never exceeds the total.
+    HeldWithinTotal,
+    /// The held amount is the sum of the transactions under dispute, less
+    /// their shortfalls, and of the fraud holds.
+    HeldMatchesDisputes,
+    /// Deposits and withdrawals leave accounts that are not active unchanged.
+    LockedUnchanged,
+    /// Balances change exactly by the postings of the operation, so no
+    /// update wrapped around.
+    NoUnderflow,
+}
+
+/// Invariant broken by handling `transaction`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub invariant: Invariant,
+    pub transaction: Transaction,
+    pub message: String,
+}
+
+/// Balances of an account before an operation, to compare with after it.
+#[derive(Debug, Clone, Copy)]
+pub struct AccountSnapshot {
+    held: u64,
+    total: u64,
+    status: AccountStatus,
+}
+
+impl AccountSnapshot {
+    pub fn of(account: &Account) -> AccountSnapshot {
+        AccountSnapshot {
+            held: account.get_held_in_cent_parts(),
+            total: account.get_total_in_cent_parts(),
+            status: account.get_status(),
+        }
+    }
+}
+
+/// Checks the account after it handled `transaction`, whether the operation
+/// was accepted or not, against the snapshot taken before. `entry` is the
+/// journal entry the account returned, `None` for a rejected operation.
+pub fn check(
+    before: &AccountSnapshot,
+    after: &Account,
+    transaction: &Transaction,
+    entry: Option<&JournalEntry>,
+) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut violated = |invariant, message: String| {
+        violations.push(Violation {
+            invariant,
+            transaction: transaction.clone(),
+            message,
+        })
+    };
+
+    let client_id = after.get_client_id();
+    let held = after.get_held_in_cent_parts();
+    let total = after.get_total_in_cent_parts();
+
+    if held > total {
+        violated(
+            Invariant::HeldWithinTotal,
+            format!("held {} exceeds total {}", held, total),
+        );
+    }
+
+    let disputed: u64 = after
+        .get_transactions()
+        .map(|record| record.held_in_cent_parts())
+        .sum::<u64>()
+        + after.get_fraud_held_in_cent_parts();
+
+    if held != disputed {
+        violated(
+            Invariant::HeldMatchesDisputes,
+            format!("held {} but {} is under dispute", held, disputed),
+        );
+    }
+
+    if before.status != AccountStatus::Active
+        && matches!(
+            transaction.transaction_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        )
+        && (held != before.held || total != before.total)
+    {
+        violated(
+            Invariant::LockedUnchanged,
+            format!(
+                "{} account changed from held {} total {} to held {} total {}",
+                before.status, before.held, before.total, held, total
+            ),
+        );
+    }
+
+    let change_of = |account| entry.map_or(0, |entry| entry.change_of(account));
+    let held_change = change_of(LedgerAccount::ClientHeld(client_id));
+    let total_change = held_change + change_of(LedgerAccount::ClientAvailable(client_id));
+
+    if held as i128 != before.held as i128 + held_change
+        || total as i128 != before.total as i128 + total_change
+    {
+        violated(
+            Invariant::NoUnderflow,
+            format!(
+                "held {} total {} after postings of {:+} and {:+} to held {} total {}",
+                held, total, held_change, total_change, before.held, before.total
+            ),
+        );
+    }
+
+    violations
+}
+
+impl fmt::Display for Invariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Invariant::HeldWithinTotal => write!(f, "held_within_total"),
+            Invariant::HeldMatchesDisputes => write!(f, "held_matches_disputes"),
+            Invariant::LockedUnchanged => write!(f, "locked_unchanged"),
+            Invariant::NoUnderflow => write!(f, "no_underflow"),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Client {} {} {} violates {}: {}",
+            self.transaction.client_id,
+            self.transaction.transaction_type,
+            self.transaction.tx_id,
+            self.invariant,
+            self.message
+        )
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+use crate::account::format_cent_delta;
+use crate::transaction::TransactionType;
+
+/// Account of the general ledger. Client accounts hold what is owed to the
+/// client, the others are the counterparties money comes from or goes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LedgerAccount {
+    ClientAvailable(u16),
+    ClientHeld(u16),
+    /// What the client owes, negative once a chargeback returned funds the
+    /// client had already withdrawn.
+    ClientReceivable(u16),
+    /// Money moving between the processor and the outside world.
+    Settlement,
+    /// Fees charged to clients, no operation charges any yet.
+    Fees,
+    /// Money returned through chargebacks.
+    ChargebackLoss,
+}
+
+/// Signed change of a ledger account in cent parts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Posting {
+    pub account: LedgerAccount,
+    pub amount: i64,
+}
+
+/// Postings of a single operation, they always sum to zero.
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub client_id: u16,
+    pub tx_id: u32,
+    pub transaction_type: TransactionType,
+    pub postings: Vec<Posting>,
+}
+
+/// Journal entries in the order they were posted and the balances of every
+/// ledger account that has been posted to.
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    balances: BTreeMap<LedgerAccount, i128>,
+    entries: Vec<JournalEntry>,
+}
+
+/// Balance of a ledger account. `expected` is the balance the client account
+/// itself reports, for client ledger accounts.
+#[derive(Debug, Clone, Copy)]
+pub struct TrialBalanceRow {
+    pub account: LedgerAccount,
+    pub balance: i128,
+    pub expected: Option<i128>,
+}
+
+/// Balances of all ledger accounts and their sum. A consistent ledger sums
+/// to zero and agrees with the balances of the client accounts.
+#[derive(Debug, Clone)]
+pub struct TrialBalance {
+    pub rows: Vec<TrialBalanceRow>,
+    pub sum: i128,
+}
+
+impl JournalEntry {
+    pub fn new(client_id: u16, tx_id: u32, transaction_type: TransactionType) -> JournalEntry {
+        JournalEntry {
+            client_id,
+            tx_id,
+            transaction_type,
+            postings: Vec::new(),
+        }
+    }
+
+    /// Moves `amount` from one account to another.
+    pub fn transfer(mut self, from: LedgerAccount, to: LedgerAccount, amount: i64) -> JournalEntry {
+        self.postings.push(Posting {
+            account: from,
+            amount: -amount,
+        });
+        self.postings.push(Posting {
+            account: to,
+            amount,
+        });
+        self
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.postings
+            .iter()
+            .map(|posting| posting.amount as i128)
+            .sum::<i128>()
+            == 0
+    }
+
+    /// Sum of the postings to one account, wide enough not to overflow.
+    pub fn change_of(&self, account: LedgerAccount) -> i128 {
+        self.postings
+            .iter()
+            .filter(|posting| posting.account == account)
+            .map(|posting| posting.amount as i128)
+            .sum()
+    }
+}
+
+impl Ledger {
+    pub fn new() -> Ledger {
+        Ledger::default()
+    }
+
+    pub fn post(&mut self, entry: JournalEntry) {
+        for posting in &entry.postings {
+            *self.balances.entry(posting.account).or_insert(0) += posting.amount as i128;
+        }
+
+        self.entries.push(entry);
+    }
+
+    pub fn balance(&self, account: LedgerAccount) -> i128 {
+        self.balances.get(&account).copied().unwrap_or(0)
+    }
+
+    /// Journal entries in posting order, showing where the money of every
+    /// balance came from.
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    pub fn trial_balance(&self) -> TrialBalance {
+        TrialBalance {
+            rows: self
+                .balances
+                .iter()
+                .map(|(account, balance)| TrialBalanceRow {
+                    account: *account,
+                    balance: *balance,
+                    expected: None,
+                })
+                .collect(),
+            sum: self.balances.values().sum(),
+        }
+    }
+}
+
+impl TrialBalanceRow {
+    pub fn is_matched(&self) -> bool {
+        self.expected
+            .is_none_or(|expected| expected == self.balance)
+    }
+}
+
+impl TrialBalance {
+    pub fn is_balanced(&self) -> bool {
+        self.sum == 0 && self.rows.iter().all(TrialBalanceRow::is_matched)
+    }
+
+    /// Client ledger accounts that disagree with the client account.
+    pub fn differences(&self) -> impl Iterator<Item = &TrialBalanceRow> {
+        self.rows.iter().filter(|row| !row.is_matched())
+    }
+}
+
+impl Serialize for TrialBalanceRow {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = s.serialize_struct("TrialBalanceRow", 3)?;
+        state.serialize_field("account", &self.account.to_string())?;
+        state.serialize_field("balance", &format_cent_delta(self.balance))?;
+        state.serialize_field("expected", &self.expected.map(format_cent_delta))?;
+        state.end()
+    }
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LedgerAccount::ClientAvailable(client_id) => {
+                write!(f, "client:{}:available", client_id)
+            }
+            LedgerAccount::ClientHeld(client_id) => write!(f, "client:{}:held", client_id),
+            LedgerAccount::ClientReceivable(client_id) => {
+                write!(f, "client:{}:receivable", client_id)
+            }
+            LedgerAccount::Settlement => write!(f, "settlement"),
+            LedgerAccount::Fees => write!(f, "fees"),
+            LedgerAccount::ChargebackLoss => write!(f, "chargeback_loss"),
+        }
+    }
+}
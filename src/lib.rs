@@ -1,3 +1,20 @@
 pub mod account;
+pub mod admin;
+pub mod audit;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+pub mod config;
+pub mod disputes;
+pub mod dry_run;
+pub mod format;
+pub mod fraud;
+pub mod input;
+pub mod invariants;
+pub mod ledger;
+pub mod limits;
 pub mod processor;
+pub mod reconcile;
+pub mod reject;
+pub mod statement;
+pub mod stats;
 pub mod transaction;
@@ -0,0 +1,244 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+use crate::account::{parse_cent_parts, Account, OperationError};
+use crate::transaction::{Transaction, TransactionType};
+
+pub(crate) const SECONDS_IN_DAY: u64 = 86_400;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitKind {
+    SingleWithdrawal,
+    DailyWithdrawal,
+    TransactionCount,
+}
+
+/// Monetary limits are configured as decimals, same as transaction amounts,
+/// and kept in cent parts.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Limits {
+    #[serde(default, deserialize_with = "deserialize_limit")]
+    pub max_withdrawal: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_limit")]
+    pub max_daily_withdrawal: Option<u64>,
+    pub max_transactions: Option<u32>,
+    pub window_seconds: Option<u64>,
+}
+
+/// A limit as written in the policy file. Toml has no decimal type, so a
+/// float is read back from its shortest text, which is the text written.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LimitValue {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+fn deserialize_limit<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = match LimitValue::deserialize(deserializer)? {
+        LimitValue::Integer(value) => value.to_string(),
+        LimitValue::Float(value) => value.to_string(),
+        LimitValue::Text(value) => value,
+    };
+
+    if text.trim_start().starts_with('-') {
+        return Err(D::Error::custom(format!("limit {} is negative", text)));
+    }
+
+    parse_cent_parts(&text).map(Some).map_err(D::Error::custom)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClientLimits {
+    pub client: u16,
+
+    #[serde(flatten)]
+    pub limits: Limits,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "LimitsConfig")]
+pub struct LimitsPolicy {
+    global: Limits,
+    clients: HashMap<u16, Limits>,
+}
+
+#[derive(Deserialize)]
+struct LimitsConfig {
+    #[serde(flatten)]
+    global: Limits,
+
+    #[serde(default)]
+    client: Vec<ClientLimits>,
+}
+
+impl From<LimitsConfig> for LimitsPolicy {
+    fn from(config: LimitsConfig) -> Self {
+        LimitsPolicy {
+            global: config.global,
+            clients: config
+                .client
+                .into_iter()
+                .map(|client_limits| (client_limits.client, client_limits.limits))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ActivityEntry {
+    pub timestamp: u64,
+    pub transaction_type: TransactionType,
+    pub amount: u64,
+}
+
+/// Accepted transactions of a single client. Entries are kept only as long as
+/// any limit window can look back, counters cover the whole account lifetime.
+#[derive(Debug, Clone, Default)]
+pub struct ClientActivity {
+    entries: VecDeque<ActivityEntry>,
+    counts: HashMap<TransactionType, u64>,
+}
+
+impl ClientActivity {
+    pub fn record(&mut self, timestamp: u64, transaction_type: TransactionType, amount: u64) {
+        self.entries.push_back(ActivityEntry {
+            timestamp,
+            transaction_type,
+            amount,
+        });
+        *self.counts.entry(transaction_type).or_insert(0) += 1;
+    }
+
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &ActivityEntry> {
+        self.entries.iter()
+    }
+
+    pub fn since(&self, since: u64) -> impl Iterator<Item = &ActivityEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.timestamp > since)
+    }
+
+    pub fn lifetime_count(&self, transaction_type: TransactionType) -> u64 {
+        self.counts.get(&transaction_type).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn prune(&mut self, now: u64, retention: u64) {
+        while let Some(entry) = self.entries.front() {
+            if entry.timestamp.saturating_add(retention) > now {
+                break;
+            }
+
+            self.entries.pop_front();
+        }
+    }
+
+    fn withdrawn_since(&self, since: u64) -> u64 {
+        self.since(since)
+            .filter(|entry| entry.transaction_type == TransactionType::Withdrawal)
+            .map(|entry| entry.amount)
+            .fold(0, u64::saturating_add)
+    }
+}
+
+impl LimitsPolicy {
+    pub fn new(global: Limits) -> LimitsPolicy {
+        LimitsPolicy {
+            global,
+            clients: HashMap::new(),
+        }
+    }
+
+    pub fn set_client_limits(&mut self, client_id: u16, limits: Limits) {
+        self.clients.insert(client_id, limits);
+    }
+
+    /// Client specific limits override the global ones field by field.
+    pub fn limits_for(&self, client_id: u16) -> Limits {
+        let global = &self.global;
+
+        match self.clients.get(&client_id) {
+            Some(client) => Limits {
+                max_withdrawal: client.max_withdrawal.or(global.max_withdrawal),
+                max_daily_withdrawal: client.max_daily_withdrawal.or(global.max_daily_withdrawal),
+                max_transactions: client.max_transactions.or(global.max_transactions),
+                window_seconds: client.window_seconds.or(global.window_seconds),
+            },
+            None => global.clone(),
+        }
+    }
+
+    /// How far back any configured limit looks, at least a day.
+    pub fn longest_window(&self) -> u64 {
+        self.clients
+            .values()
+            .chain(std::iter::once(&self.global))
+            .filter_map(|limits| limits.window_seconds)
+            .fold(SECONDS_IN_DAY, u64::max)
+    }
+
+    pub fn check(
+        &self,
+        account: &Account,
+        transaction: &Transaction,
+        now: u64,
+    ) -> Result<(), OperationError> {
+        let limits = self.limits_for(transaction.client_id);
+        let window = limits.window_seconds.unwrap_or(SECONDS_IN_DAY);
+        let activity = account.get_activity();
+
+        let exceeded = |kind| {
+            Err(OperationError::LimitExceeded(
+                transaction.client_id,
+                transaction.tx_id,
+                kind,
+            ))
+        };
+
+        if let Some(max_transactions) = limits.max_transactions {
+            if activity.since(now.saturating_sub(window)).count() >= max_transactions as usize {
+                return exceeded(LimitKind::TransactionCount);
+            }
+        }
+
+        if let (TransactionType::Withdrawal, Some(_)) =
+            (&transaction.transaction_type, transaction.amount)
+        {
+            let amount = transaction.amount_in_cent_parts();
+
+            if let Some(max_withdrawal) = limits.max_withdrawal {
+                if amount > max_withdrawal {
+                    return exceeded(LimitKind::SingleWithdrawal);
+                }
+            }
+
+            if let Some(max_daily_withdrawal) = limits.max_daily_withdrawal {
+                let withdrawn = activity.withdrawn_since(now.saturating_sub(SECONDS_IN_DAY));
+
+                if withdrawn.saturating_add(amount) > max_daily_withdrawal {
+                    return exceeded(LimitKind::DailyWithdrawal);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitKind::SingleWithdrawal => write!(f, "single withdrawal limit"),
+            LimitKind::DailyWithdrawal => write!(f, "daily withdrawal limit"),
+            LimitKind::TransactionCount => write!(f, "transaction count limit"),
+        }
+    }
+}
@@ -1,47 +1,988 @@
-use std::env;
 use std::error::Error;
-use std::io;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use rust_test::account::{format_cent_delta, Account};
+use rust_test::audit::{self, AuditLog};
+#[cfg(feature = "parquet")]
+use rust_test::columnar;
+use rust_test::config::PolicyConfig;
+use rust_test::disputes;
+use rust_test::format::{Format, RecordWriter};
+use rust_test::input::{self, InputRecord, RecordResult};
+use rust_test::processor::{current_timestamp, PaymentProcessor, ProcessedTransaction};
+use rust_test::reconcile::{self, ExpectedBalance};
+use rust_test::reject::Reject;
+use rust_test::statement::AsOf;
+use rust_test::transaction::parse_timestamp;
+
+const EXIT_FAILURE: u8 = 1;
+const EXIT_REJECTED: u8 = 3;
+const EXIT_BREAKS: u8 = 4;
+
+type OutputWriter = RecordWriter<Box<dyn Write>>;
+
+/// Processes payment transactions and outputs the resulting client accounts.
+#[derive(Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    options: Options,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Process transactions and write the final account balances (default)
+    Process(Options),
+
+    /// Preview processing: write the outcome of every transaction and a summary
+    /// of the changes, without keeping any state
+    Validate(Options),
+
+    /// Process transactions and write the outcome of every transaction
+    Replay(Options),
+
+    /// Process transactions and write statistics of the run, as text or json
+    Report(Options),
+
+    /// Process transactions and write the movements of one client with the
+    /// resulting balances
+    Statement(StatementOptions),
+
+    /// Process transactions and write the balance of one client as it was
+    /// after a given row, time or transaction
+    Balance(BalanceOptions),
+
+    /// Process transactions and compare the final accounts with expected
+    /// balances, writing every difference
+    Reconcile(ReconcileOptions),
+
+    /// Process transactions and write the disputes still open, with their age
+    Disputes(DisputesOptions),
+
+    /// Process transactions and write the balance of every general ledger
+    /// account, checking that the ledger sums to zero
+    TrialBalance(Options),
+
+    /// Walk the hash chain of an audit log and report the first broken link
+    VerifyAudit(VerifyAuditOptions),
+}
+
+#[derive(Args)]
+struct Options {
+    /// Transactions csv files, processed in the given order. `-` reads stdin,
+    /// gzip and zstd compressed files are decompressed
+    #[arg(value_name = "INPUT", required_unless_present = "input")]
+    input_paths: Vec<PathBuf>,
+
+    /// Transactions csv file, same as the positional arguments
+    #[arg(short, long, value_name = "PATH", conflicts_with = "input_paths")]
+    input: Vec<PathBuf>,
+
+    /// Interleave the inputs by transaction timestamp instead of reading them one after another
+    #[arg(long)]
+    merge: bool,
+
+    /// Output file, stdout when omitted
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Output format: csv, json or ndjson. Detected from the output file
+    /// extension when omitted, csv otherwise
+    #[arg(short, long, value_name = "FORMAT")]
+    format: Option<Format>,
+
+    /// Order of the written accounts
+    #[arg(long, value_enum, default_value_t = AccountOrder::Client)]
+    sort: AccountOrder,
+
+    /// Only write locked accounts
+    #[arg(long)]
+    only_locked: bool,
+
+    /// Only write accounts with a non zero total or held balance
+    #[arg(long)]
+    only_non_zero: bool,
+
+    /// Input format: csv, json or ndjson. Detected from each input file
+    /// extension when omitted, csv otherwise
+    #[arg(long, value_name = "FORMAT")]
+    input_format: Option<Format>,
+
+    /// All or nothing: stop at the first row that cannot be parsed or is
+    /// rejected, roll back the whole input and write no output
+    #[arg(long)]
+    strict: bool,
+
+    /// Where parse errors and rejected transactions are reported, stderr when omitted
+    #[arg(long, value_name = "PATH")]
+    errors: Option<PathBuf>,
+
+    /// Where rows that could not be parsed or were rejected are written, with
+    /// their line, error code and message. Csv, json or ndjson by extension
+    #[arg(long, value_name = "PATH")]
+    rejects: Option<PathBuf>,
+
+    /// Where every processed transaction and its outcome is written as a
+    /// hash-chained newline delimited json audit log
+    #[arg(long, value_name = "PATH")]
+    audit: Option<PathBuf>,
+
+    /// Where fraud alerts are written as csv, stderr when omitted
+    #[arg(long, value_name = "PATH")]
+    alerts: Option<PathBuf>,
+
+    /// Check account invariants after every operation and report violations
+    /// with the errors, for debugging
+    #[arg(long)]
+    check_invariants: bool,
+
+    /// Policy file in toml format
+    #[arg(short, long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Also write the final accounts to a parquet file
+    #[cfg(feature = "parquet")]
+    #[arg(long, value_name = "PATH")]
+    parquet_accounts: Option<PathBuf>,
+
+    /// Also write every processed transaction with its outcome to a parquet file
+    #[cfg(feature = "parquet")]
+    #[arg(long, value_name = "PATH")]
+    parquet_ledger: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct StatementOptions {
+    /// Client to write the statement for
+    #[arg(long)]
+    client: u16,
+
+    /// Only movements at or after this time, epoch seconds or RFC 3339
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    from: Option<u64>,
+
+    /// Only movements at or before this time, epoch seconds or RFC 3339
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    to: Option<u64>,
+
+    /// Printable plain text layout instead of csv or json records
+    #[arg(long, conflicts_with = "format")]
+    text: bool,
+
+    #[command(flatten)]
+    options: Options,
+}
+
+#[derive(Args)]
+#[group(id = "as_of", required = true, multiple = false, args = ["sequence", "at", "tx"])]
+struct BalanceOptions {
+    /// Client to query
+    #[arg(long)]
+    client: u16,
+
+    /// Balance after the input row with this sequence number
+    #[arg(long)]
+    sequence: Option<u64>,
+
+    /// Balance after the last movement at or before this time, epoch
+    /// seconds or RFC 3339
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    at: Option<u64>,
+
+    /// Balance after the deposit or withdrawal with this transaction id
+    #[arg(long)]
+    tx: Option<u32>,
+
+    #[command(flatten)]
+    options: Options,
+}
+
+#[derive(Args)]
+struct ReconcileOptions {
+    /// Expected balances csv with client, available, held, total and locked columns
+    #[arg(long, value_name = "PATH")]
+    expected: PathBuf,
+
+    #[command(flatten)]
+    options: Options,
+}
+
+#[derive(Args)]
+struct DisputesOptions {
+    /// Time the disputes are aged at, epoch seconds or RFC 3339. The latest
+    /// transaction timestamp when omitted, the current time without any
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    as_of: Option<u64>,
+
+    /// Write the count and held amount per aging bucket instead of the
+    /// individual disputes
+    #[arg(long)]
+    aging: bool,
+
+    #[command(flatten)]
+    options: Options,
+}
+
+#[derive(Args)]
+struct VerifyAuditOptions {
+    /// Audit log written with `--audit`
+    #[arg(value_name = "LOG")]
+    path: PathBuf,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AccountOrder {
+    /// Ascending client id
+    Client,
+    /// Descending total balance, ties by ascending client id
+    Balance,
+}
+
+#[derive(Default)]
+struct RunSummary {
+    rows: u64,
+    parse_errors: u64,
+    accepted: u64,
+    rejected: u64,
+}
+
+impl RunSummary {
+    fn has_failures(&self) -> bool {
+        self.parse_errors > 0 || self.rejected > 0
+    }
+}
+
+/// Returned by a strict run whose input was rolled back, so that no command
+/// writes output from the untouched processor. The process exits with
+/// `EXIT_REJECTED`.
+#[derive(Debug)]
+struct RolledBack;
+
+impl fmt::Display for RolledBack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "input rolled back")
+    }
+}
+
+impl Error for RolledBack {}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        None => process(&cli.options),
+        Some(Command::Process(options)) => process(&options),
+        Some(Command::Validate(options)) => validate(&options),
+        Some(Command::Replay(options)) => replay(&options),
+        Some(Command::Report(options)) => report(&options),
+        Some(Command::Statement(options)) => statement(&options),
+        Some(Command::Balance(options)) => balance(&options),
+        Some(Command::Reconcile(options)) => reconcile(&options),
+        Some(Command::Disputes(options)) => open_disputes(&options),
+        Some(Command::TrialBalance(options)) => trial_balance(&options),
+        Some(Command::VerifyAudit(options)) => verify_audit(&options),
+    };
 
-use rust_test::processor::PaymentProcessor;
+    match result {
+        Ok(code) => code,
+        Err(e) if e.is::<RolledBack>() => ExitCode::from(EXIT_REJECTED),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn process(options: &Options) -> Result<ExitCode, Box<dyn Error>> {
+    let mut processor = create_processor(options)?;
+    let mut errors = open_errors(options)?;
+    let mut ledger = Vec::new();
+    run(options, &mut processor, &mut errors, |processed| {
+        if keeps_ledger(options) {
+            ledger.push(processed.clone());
+        }
+
+        Ok(())
+    })?;
+
+    let mut writer = open_record_writer(options)?;
+
+    select_accounts(options, &processor)
+        .into_iter()
+        .try_for_each(|account| writer.write(account))?;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut reader = csv::Reader::from_path(parse_input_path_argument())?;
-    let mut processor = PaymentProcessor::new();
+    writer.finish()?;
+
+    write_parquet(options, &processor, &ledger)?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Runs the input against a copy of the processor, writing the outcome of
+/// every row and a summary of what processing it would change.
+fn validate(options: &Options) -> Result<ExitCode, Box<dyn Error>> {
+    let processor = create_processor(options)?;
+    let mut errors = open_errors(options)?;
+    let mut rejects = open_rejects(options)?;
+    let mut summary = RunSummary::default();
+    let mut records = Vec::new();
+    let mut rows = Vec::new();
+
+    for result in read_inputs(options)? {
+        summary.rows += 1;
 
-    for result in reader.deserialize() {
         match result {
-            Ok(transaction) => {
-                processor.process(transaction);
+            Ok(record) => {
+                records.push(record);
+                rows.push(summary.rows);
+            }
+            Err(e) if e.fatal => return Err(Box::new(e)),
+            Err(e) => {
+                summary.parse_errors += 1;
+                writeln!(errors, "Deserialization error occured: {}", e)?;
+
+                if let Some(writer) = rejects.as_mut() {
+                    writer.write(&Reject::from_parse_error(&e))?;
+                }
+
+                if options.strict {
+                    break;
+                }
             }
-            Err(e) => eprintln!("Deserialization error occured: {}", e),
         }
     }
 
-    let mut writer = csv::Writer::from_writer(io::stdout());
-    
-    processor
-        .get_accounts()
+    let dry_run = processor.dry_run(records.iter().map(|record| record.transaction.clone()));
+    let mut writer = open_record_writer(options)?;
+
+    for ((processed, record), row) in dry_run.outcomes.iter().zip(&records).zip(rows) {
+        if let Some(e) = &processed.error {
+            writeln!(errors, "Transaction error occured: {}", e)?;
+
+            if let Some(writer) = rejects.as_mut() {
+                writer.write(&Reject::from_operation_error(record, e))?;
+            }
+        }
+
+        writer.write(&ProcessedTransaction {
+            sequence: row,
+            ..processed.clone()
+        })?;
+    }
+
+    writer.finish()?;
+
+    if let Some(writer) = rejects {
+        writer.finish()?;
+    }
+
+    writeln!(
+        errors,
+        "{}invalid: {}",
+        dry_run.summary, summary.parse_errors
+    )?;
+
+    if summary.parse_errors > 0 || dry_run.summary.rejected > 0 {
+        return Ok(ExitCode::from(EXIT_REJECTED));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn replay(options: &Options) -> Result<ExitCode, Box<dyn Error>> {
+    let mut processor = create_processor(options)?;
+    let mut errors = open_errors(options)?;
+    let mut outcomes = Vec::new();
+    run(options, &mut processor, &mut errors, |processed| {
+        outcomes.push(processed.clone());
+
+        Ok(())
+    })?;
+
+    let mut writer = open_record_writer(options)?;
+
+    outcomes
+        .iter()
+        .try_for_each(|processed| writer.write(processed))?;
+
+    writer.finish()?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Writes the processing statistics as text, or as a json document when a
+/// json output format is selected.
+fn report(options: &Options) -> Result<ExitCode, Box<dyn Error>> {
+    let mut processor = create_processor(options)?;
+    let mut errors = open_errors(options)?;
+    run(options, &mut processor, &mut errors, |_| Ok(()))?;
+    let stats = processor.stats();
+    let mut output = open_output(options)?;
+
+    match output_format(options) {
+        Format::Csv => writeln!(output, "{}", stats)?,
+        Format::Json | Format::Ndjson => {
+            serde_json::to_writer(&mut output, &stats)?;
+            writeln!(output)?;
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn statement(statement_options: &StatementOptions) -> Result<ExitCode, Box<dyn Error>> {
+    let options = &statement_options.options;
+    let mut processor = create_processor(options)?;
+    let mut errors = open_errors(options)?;
+    run(options, &mut processor, &mut errors, |_| Ok(()))?;
+    let client_id = statement_options.client;
+    let statement = processor
+        .statement(client_id, statement_options.from, statement_options.to)
+        .ok_or_else(|| format!("client {} not found", client_id))?;
+
+    if statement_options.text {
+        writeln!(open_output(options)?, "{}", statement)?;
+    } else {
+        let mut writer = open_record_writer(options)?;
+
+        statement
+            .entries
+            .iter()
+            .try_for_each(|entry| writer.write(entry))?;
+
+        writer.finish()?;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn balance(balance_options: &BalanceOptions) -> Result<ExitCode, Box<dyn Error>> {
+    let options = &balance_options.options;
+    let as_of = match (
+        balance_options.sequence,
+        balance_options.at,
+        balance_options.tx,
+    ) {
+        (Some(sequence), _, _) => AsOf::Sequence(sequence),
+        (_, Some(timestamp), _) => AsOf::Timestamp(timestamp),
+        (_, _, Some(tx_id)) => AsOf::Transaction(tx_id),
+        (None, None, None) => return Err("one of --sequence, --at or --tx is required".into()),
+    };
+    let mut processor = create_processor(options)?;
+    let mut errors = open_errors(options)?;
+    run(options, &mut processor, &mut errors, |_| Ok(()))?;
+    let client_id = balance_options.client;
+    let balance = processor
+        .balance_as_of(client_id, as_of)
+        .ok_or_else(|| format!("no balance of client {} after {}", client_id, as_of))?;
+    let mut writer = open_record_writer(options)?;
+
+    writer.write(&balance)?;
+    writer.finish()?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Breaks are written as records, the process exits with `EXIT_BREAKS` when
+/// there is any. Every processed account is compared, the account filters
+/// of the output do not apply.
+fn reconcile(reconcile_options: &ReconcileOptions) -> Result<ExitCode, Box<dyn Error>> {
+    let options = &reconcile_options.options;
+    let expected = ExpectedBalance::read_csv(input::open(&reconcile_options.expected)?)?;
+    let mut processor = create_processor(options)?;
+    let mut errors = open_errors(options)?;
+    run(options, &mut processor, &mut errors, |_| Ok(()))?;
+    let breaks = reconcile::reconcile(&expected, processor.get_accounts().values());
+    let mut writer = open_record_writer(options)?;
+
+    breaks
+        .iter()
+        .try_for_each(|reconcile_break| writer.write(reconcile_break))?;
+    writer.finish()?;
+
+    writeln!(
+        errors,
+        "Reconciliation: {} expected clients, {} breaks",
+        expected.len(),
+        breaks.len()
+    )?;
+
+    if !breaks.is_empty() {
+        return Ok(ExitCode::from(EXIT_BREAKS));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn open_disputes(disputes_options: &DisputesOptions) -> Result<ExitCode, Box<dyn Error>> {
+    let options = &disputes_options.options;
+    let mut processor = create_processor(options)?;
+    let mut errors = open_errors(options)?;
+    run(options, &mut processor, &mut errors, |_| Ok(()))?;
+    let now = disputes_options
+        .as_of
+        .or_else(|| processor.latest_timestamp())
+        .unwrap_or_else(current_timestamp);
+    let open_disputes = processor.open_disputes(now);
+    let mut writer = open_record_writer(options)?;
+
+    if disputes_options.aging {
+        disputes::aging_totals(&open_disputes)
+            .iter()
+            .try_for_each(|total| writer.write(total))?;
+    } else {
+        open_disputes
+            .iter()
+            .try_for_each(|dispute| writer.write(dispute))?;
+    }
+
+    writer.finish()?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Exits with `EXIT_BREAKS` when the ledger does not sum to zero or does not
+/// match the account balances.
+fn trial_balance(options: &Options) -> Result<ExitCode, Box<dyn Error>> {
+    let mut processor = create_processor(options)?;
+    let mut errors = open_errors(options)?;
+    run(options, &mut processor, &mut errors, |_| Ok(()))?;
+    let trial_balance = processor.trial_balance();
+    let mut writer = open_record_writer(options)?;
+
+    trial_balance
+        .rows
         .iter()
-        .map(|(_, account)| account)
-        .try_for_each(|account| writer.serialize(account))?;
+        .try_for_each(|row| writer.write(row))?;
+    writer.finish()?;
+
+    writeln!(
+        errors,
+        "Trial balance: {} entries, sum {}",
+        processor.get_ledger().entries().len(),
+        format_cent_delta(trial_balance.sum)
+    )?;
+
+    for row in trial_balance.differences() {
+        writeln!(
+            errors,
+            "Ledger account {} is {} but the client account reports {}",
+            row.account,
+            format_cent_delta(row.balance),
+            format_cent_delta(row.expected.unwrap_or_default())
+        )?;
+    }
+
+    if !trial_balance.is_balanced() {
+        return Ok(ExitCode::from(EXIT_BREAKS));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Exits with `EXIT_BREAKS` at the first record that does not belong to the
+/// chain.
+fn verify_audit(options: &VerifyAuditOptions) -> Result<ExitCode, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(&options.path)?);
+
+    match audit::verify(reader) {
+        Ok(records) => {
+            println!("Audit log verified: {} records", records);
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(broken) => {
+            eprintln!("Audit log broken at {}", broken);
+            Ok(ExitCode::from(EXIT_BREAKS))
+        }
+    }
+}
+
+/// Feeds every input row through the processor, reporting parse errors,
+/// rejections, alerts and expired disputes along the way.
+fn run<F>(
+    options: &Options,
+    processor: &mut PaymentProcessor,
+    errors: &mut dyn Write,
+    mut on_processed: F,
+) -> Result<RunSummary, Box<dyn Error>>
+where
+    F: FnMut(&ProcessedTransaction) -> Result<(), Box<dyn Error>>,
+{
+    if options.strict {
+        return run_batch(options, processor, errors, on_processed);
+    }
+
+    let records = read_inputs(options)?;
+    let mut rejects = open_rejects(options)?;
+    let mut alerts = open_alerts(options)?;
+    let mut audit = open_audit(options)?;
+    let mut summary = RunSummary::default();
+
+    for result in records {
+        summary.rows += 1;
+
+        let record = match result {
+            Ok(record) => record,
+            Err(e) if e.fatal => return Err(Box::new(e)),
+            Err(e) => {
+                summary.parse_errors += 1;
+                processor.record_parse_error();
+                writeln!(errors, "Deserialization error occured: {}", e)?;
+
+                if let Some(writer) = rejects.as_mut() {
+                    writer.write(&Reject::from_parse_error(&e))?;
+                }
+
+                continue;
+            }
+        };
+
+        let processed = ProcessedTransaction {
+            sequence: summary.rows,
+            error: processor.try_process(record.transaction.clone()).err(),
+            transaction: record.transaction.clone(),
+        };
+
+        match &processed.error {
+            None => summary.accepted += 1,
+            Some(e) => {
+                summary.rejected += 1;
+                writeln!(errors, "Transaction error occured: {}", e)?;
+
+                if let Some(writer) = rejects.as_mut() {
+                    writer.write(&Reject::from_operation_error(&record, e))?;
+                }
+            }
+        }
+
+        if let Some(log) = audit.as_mut() {
+            log.append(&processed)?;
+        }
+
+        on_processed(&processed)?;
+        report_events(processor, &mut alerts, errors)?;
+    }
+
+    alerts.flush()?;
+
+    if let Some(writer) = rejects {
+        writer.finish()?;
+    }
+
+    if let Some(log) = audit {
+        log.finish()?;
+    }
+
+    Ok(summary)
+}
+
+/// Strict variant of `run`: the input is applied as a single batch, so the
+/// first invalid or rejected row leaves the processor untouched and no
+/// transaction is reported as processed.
+fn run_batch<F>(
+    options: &Options,
+    processor: &mut PaymentProcessor,
+    errors: &mut dyn Write,
+    mut on_processed: F,
+) -> Result<RunSummary, Box<dyn Error>>
+where
+    F: FnMut(&ProcessedTransaction) -> Result<(), Box<dyn Error>>,
+{
+    let mut rejects = open_rejects(options)?;
+    let mut summary = RunSummary::default();
+    let mut records: Vec<InputRecord> = Vec::new();
+
+    for result in read_inputs(options)? {
+        summary.rows += 1;
+
+        match result {
+            Ok(record) => records.push(record),
+            Err(e) if e.fatal => return Err(Box::new(e)),
+            Err(e) => {
+                summary.parse_errors += 1;
+                processor.record_parse_error();
+                writeln!(errors, "Deserialization error occured: {}", e)?;
+
+                if let Some(writer) = rejects.as_mut() {
+                    writer.write(&Reject::from_parse_error(&e))?;
+                }
+
+                break;
+            }
+        }
+    }
+
+    if summary.parse_errors == 0 {
+        let transactions = records.iter().map(|record| record.transaction.clone());
+
+        match processor.process_batch(transactions) {
+            Ok(_) => summary.accepted = records.len() as u64,
+            Err(e) => {
+                summary.rows = e.index as u64 + 1;
+                summary.rejected += 1;
+                writeln!(errors, "Transaction error occured: {}", e.error)?;
+
+                if let Some(writer) = rejects.as_mut() {
+                    writer.write(&Reject::from_operation_error(&records[e.index], &e.error))?;
+                }
+            }
+        }
+    }
+
+    if let Some(writer) = rejects {
+        writer.finish()?;
+    }
+
+    if summary.has_failures() {
+        return Err(Box::new(RolledBack));
+    }
+
+    let mut audit = open_audit(options)?;
+
+    for (index, record) in records.into_iter().enumerate() {
+        let processed = ProcessedTransaction {
+            sequence: index as u64 + 1,
+            transaction: record.transaction,
+            error: None,
+        };
+
+        if let Some(log) = audit.as_mut() {
+            log.append(&processed)?;
+        }
+
+        on_processed(&processed)?;
+    }
+
+    if let Some(log) = audit {
+        log.finish()?;
+    }
+
+    let mut alerts = open_alerts(options)?;
+
+    report_events(processor, &mut alerts, errors)?;
+
+    alerts.flush()?;
+
+    Ok(summary)
+}
+
+/// Writes alerts and expired disputes raised since the last call.
+fn report_events(
+    processor: &mut PaymentProcessor,
+    alerts: &mut csv::Writer<Box<dyn Write>>,
+    errors: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    for alert in processor.take_alerts() {
+        alerts.serialize(&alert)?;
+    }
+
+    for expired in processor.take_expired_disputes() {
+        writeln!(errors, "Dispute expired: {}", expired)?;
+    }
+
+    for violation in processor.take_violations() {
+        writeln!(errors, "Invariant violation: {}", violation)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn keeps_ledger(options: &Options) -> bool {
+    options.parquet_ledger.is_some()
+}
+
+#[cfg(not(feature = "parquet"))]
+fn keeps_ledger(_options: &Options) -> bool {
+    false
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(
+    options: &Options,
+    processor: &PaymentProcessor,
+    ledger: &[ProcessedTransaction],
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &options.parquet_accounts {
+        columnar::write_accounts(path, processor.get_accounts().values())?;
+    }
+
+    if let Some(path) = &options.parquet_ledger {
+        columnar::write_ledger(path, ledger)?;
+    }
 
     Ok(())
 }
 
-fn parse_input_path_argument() -> String {
-    let args: Vec<String> = env::args().collect();
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(
+    _options: &Options,
+    _processor: &PaymentProcessor,
+    _ledger: &[ProcessedTransaction],
+) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+fn select_accounts<'a>(options: &Options, processor: &'a PaymentProcessor) -> Vec<&'a Account> {
+    let mut accounts: Vec<&Account> = processor
+        .get_accounts()
+        .values()
+        .filter(|account| !options.only_locked || account.is_locked())
+        .filter(|account| {
+            !options.only_non_zero
+                || account.get_total_in_cent_parts() != 0
+                || account.get_held_in_cent_parts() != 0
+                || account.get_owed_in_cent_parts() != 0
+        })
+        .collect();
+
+    if let AccountOrder::Balance = options.sort {
+        accounts.sort_by(|a, b| {
+            b.get_total_in_cent_parts()
+                .cmp(&a.get_total_in_cent_parts())
+        });
+    }
+
+    accounts
+}
+
+fn create_processor(options: &Options) -> Result<PaymentProcessor, Box<dyn Error>> {
+    let policy = match &options.config {
+        Some(path) => PolicyConfig::from_path(path)?,
+        None => PolicyConfig::default(),
+    };
+
+    let mut processor = PaymentProcessor::with_policy(policy);
+
+    processor.set_check_invariants(options.check_invariants);
+
+    Ok(processor)
+}
+
+fn read_inputs(
+    options: &Options,
+) -> Result<Box<dyn Iterator<Item = RecordResult>>, Box<dyn Error>> {
+    let paths: Vec<&Path> = options
+        .input_paths
+        .iter()
+        .chain(options.input.iter())
+        .map(PathBuf::as_path)
+        .collect();
+
+    if paths.iter().filter(|path| **path == Path::new("-")).count() > 1 {
+        return Err("stdin can only be read once".into());
+    }
+
+    Ok(input::read_all(
+        &paths,
+        options.input_format,
+        options.merge,
+    )?)
+}
+
+fn open_output(options: &Options) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    match &options.output {
+        Some(path) => Ok(Box::new(File::create(path)?)),
+        None => Ok(Box::new(io::stdout())),
+    }
+}
+
+fn open_record_writer(options: &Options) -> Result<OutputWriter, Box<dyn Error>> {
+    Ok(RecordWriter::new(
+        open_output(options)?,
+        output_format(options),
+    ))
+}
 
-    if args.len() <= 1 {
-        panic!("No arguments provided");
+/// Format from the flag, else from the output extension, csv otherwise.
+fn output_format(options: &Options) -> Format {
+    options
+        .format
+        .or_else(|| options.output.as_deref().and_then(Format::from_path))
+        .unwrap_or(Format::Csv)
+}
+
+fn open_errors(options: &Options) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    match &options.errors {
+        Some(path) => Ok(Box::new(File::create(path)?)),
+        None => Ok(Box::new(io::stderr())),
     }
+}
+
+/// Alerts are kept apart from the error stream, as csv records of their own.
+fn open_alerts(options: &Options) -> Result<csv::Writer<Box<dyn Write>>, Box<dyn Error>> {
+    let writer: Box<dyn Write> = match &options.alerts {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stderr()),
+    };
+
+    Ok(csv::Writer::from_writer(writer))
+}
+
+fn open_rejects(options: &Options) -> Result<Option<OutputWriter>, Box<dyn Error>> {
+    let path = match &options.rejects {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let format = Format::from_path(path).unwrap_or(Format::Csv);
+
+    Ok(Some(RecordWriter::new(
+        Box::new(File::create(path)?),
+        format,
+    )))
+}
+
+/// Appends to an existing audit log after verifying it, so the chain carries
+/// on from its last record instead of starting over.
+fn open_audit(options: &Options) -> Result<Option<AuditLog<BufWriter<File>>>, Box<dyn Error>> {
+    let path = match &options.audit {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let prev_hash = match File::open(path) {
+        Ok(file) => audit::last_hash(BufReader::new(file)).map_err(|broken| {
+            format!("audit log {} is broken at {}", path.display(), broken)
+        })?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => audit::GENESIS_HASH.to_string(),
+        Err(e) => return Err(Box::new(e)),
+    };
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    Ok(Some(AuditLog::resume(BufWriter::new(file), prev_hash)))
+}
 
-    args[1].clone()
+fn parse_time(text: &str) -> Result<u64, String> {
+    parse_timestamp(text)?.ok_or_else(|| "empty time".to_string())
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
+    use std::collections::BTreeMap;
+
+    use clap::Parser;
+    use proptest::prelude::*;
+
+    use super::{select_accounts, Cli, Command};
     use rust_test::{
-        processor::PaymentProcessor, transaction::Transaction, transaction::TransactionType,
+        account::Account, account::AccountStatus, account::DisputeStatus, account::OperationError,
+        admin::AdminActionType, admin::AdminTransaction, admin::ReasonCode, audit, audit::AuditLog,
+        config::ExpiryAction, config::PolicyConfig, disputes, disputes::AgingBucket,
+        format::Format, format::RecordWriter, fraud::FraudAction, fraud::FraudRule,
+        fraud::RuleContext, input::MergeByTimestamp, input::RecordReader, input::TransactionReader,
+        invariants, invariants::AccountSnapshot, invariants::Invariant, ledger::JournalEntry,
+        ledger::LedgerAccount, limits::LimitKind, processor::PaymentProcessor,
+        processor::ProcessedTransaction, reconcile, reconcile::BreakKind,
+        reconcile::ExpectedBalance, reject::Reject, statement::AsOf, transaction::Transaction,
+        transaction::TransactionType,
     };
 
     #[test]
@@ -51,9 +992,10 @@ mod tests {
 
         let transaction = Transaction {
             client_id,
-            amount: Some(2.25),
+            amount: Some(22500),
             transaction_type: TransactionType::Deposit,
             tx_id: 3,
+            timestamp: None,
         };
 
         processor.process(transaction);
@@ -68,9 +1010,10 @@ mod tests {
 
         let transaction = Transaction {
             client_id,
-            amount: Some(-25.0),
+            amount: Some(-250000),
             transaction_type: TransactionType::Deposit,
             tx_id: 3,
+            timestamp: None,
         };
 
         processor.process(transaction);
@@ -88,13 +1031,14 @@ mod tests {
     fn can_deposit() {
         let mut processor = PaymentProcessor::new();
         let client_id = 10;
-        let amount = 22.5;
+        let amount = 225000;
 
         let transaction = Transaction {
             client_id,
             amount: Some(amount),
             transaction_type: TransactionType::Deposit,
             tx_id: 5,
+            timestamp: None,
         };
 
         processor.process(transaction);
@@ -102,8 +1046,8 @@ mod tests {
         let accounts = processor.get_accounts();
         let account = accounts.get(&client_id).unwrap();
 
-        assert_eq!(account.get_total(), amount);
-        assert_eq!(account.get_available(), amount);
+        assert_eq!(account.get_total_in_cent_parts(), amount as u64);
+        assert_eq!(account.get_available_in_cent_parts(), amount as u64);
         assert_eq!(account.get_held(), 0.0);
         assert_eq!(account.is_locked(), false);
     }
@@ -112,14 +1056,15 @@ mod tests {
     fn can_withdraw() {
         let mut processor = PaymentProcessor::new();
         let client_id = 11;
-        let amount_deposit = 15.5;
-        let amount_withdraw = 10.0;
+        let amount_deposit = 155000;
+        let amount_withdraw = 100000;
 
         let transaction_deposit = Transaction {
             client_id,
             amount: Some(amount_deposit),
             transaction_type: TransactionType::Deposit,
             tx_id: 6,
+            timestamp: None,
         };
 
         processor.process(transaction_deposit);
@@ -129,6 +1074,7 @@ mod tests {
             amount: Some(amount_withdraw),
             transaction_type: TransactionType::Withdrawal,
             tx_id: 7,
+            timestamp: None,
         };
 
         processor.process(transaction_withdraw);
@@ -136,8 +1082,8 @@ mod tests {
         let accounts = processor.get_accounts();
         let account = accounts.get(&client_id).unwrap();
 
-        assert_eq!(account.get_total(), amount_deposit - amount_withdraw);
-        assert_eq!(account.get_available(), amount_deposit - amount_withdraw);
+        assert_eq!(account.get_total_in_cent_parts(), (amount_deposit - amount_withdraw) as u64);
+        assert_eq!(account.get_available_in_cent_parts(), (amount_deposit - amount_withdraw) as u64);
         assert_eq!(account.get_held(), 0.0);
         assert_eq!(account.is_locked(), false);
     }
@@ -146,14 +1092,15 @@ mod tests {
     fn cannot_withdraw_higher_amount_than_available() {
         let mut processor = PaymentProcessor::new();
         let client_id = 11;
-        let amount_deposit = 15.5;
-        let amount_withdraw = 16.0;
+        let amount_deposit = 155000;
+        let amount_withdraw = 160000;
 
         let transaction_deposit = Transaction {
             client_id,
             amount: Some(amount_deposit),
             transaction_type: TransactionType::Deposit,
             tx_id: 6,
+            timestamp: None,
         };
 
         processor.process(transaction_deposit);
@@ -163,6 +1110,7 @@ mod tests {
             amount: Some(amount_withdraw),
             transaction_type: TransactionType::Withdrawal,
             tx_id: 7,
+            timestamp: None,
         };
 
         processor.process(transaction_withdraw);
@@ -171,8 +1119,8 @@ mod tests {
         let account = accounts.get(&client_id).unwrap();
 
         assert_eq!(amount_withdraw > amount_deposit, true);
-        assert_eq!(account.get_total(), amount_deposit);
-        assert_eq!(account.get_available(), amount_deposit);
+        assert_eq!(account.get_total_in_cent_parts(), amount_deposit as u64);
+        assert_eq!(account.get_available_in_cent_parts(), amount_deposit as u64);
         assert_eq!(account.get_held(), 0.0);
         assert_eq!(account.is_locked(), false);
     }
@@ -181,8 +1129,8 @@ mod tests {
     fn can_dispute() {
         let mut processor = PaymentProcessor::new();
         let client_id = 11;
-        let amount_deposit = 25.5;
-        let amount_withdraw = 10.0;
+        let amount_deposit = 255000;
+        let amount_withdraw = 100000;
         let withdraw_tx_id = 7;
 
         let transaction_deposit = Transaction {
@@ -190,6 +1138,7 @@ mod tests {
             amount: Some(amount_deposit),
             transaction_type: TransactionType::Deposit,
             tx_id: 6,
+            timestamp: None,
         };
 
         processor.process(transaction_deposit);
@@ -199,6 +1148,7 @@ mod tests {
             amount: Some(amount_withdraw),
             transaction_type: TransactionType::Withdrawal,
             tx_id: withdraw_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_withdraw);
@@ -208,6 +1158,7 @@ mod tests {
             amount: None,
             transaction_type: TransactionType::Dispute,
             tx_id: withdraw_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_dispute);
@@ -215,9 +1166,12 @@ mod tests {
         let accounts = processor.get_accounts();
         let account = accounts.get(&client_id).unwrap();
 
-        assert_eq!(account.get_total(), amount_deposit - amount_withdraw);
-        assert_eq!(account.get_available(), amount_deposit - amount_withdraw);
-        assert_eq!(account.get_held(), amount_withdraw);
+        assert_eq!(account.get_total_in_cent_parts(), (amount_deposit - amount_withdraw) as u64);
+        assert_eq!(
+            account.get_available_in_cent_parts(),
+            (amount_deposit - amount_withdraw - amount_withdraw) as u64
+        );
+        assert_eq!(account.get_held_in_cent_parts(), amount_withdraw as u64);
         assert_eq!(account.is_locked(), false);
     }
 
@@ -225,8 +1179,8 @@ mod tests {
     fn can_resolve() {
         let mut processor = PaymentProcessor::new();
         let client_id = 11;
-        let amount_deposit = 25.5;
-        let amount_withdraw = 10.0;
+        let amount_deposit = 255000;
+        let amount_withdraw = 100000;
         let withdraw_tx_id = 7;
 
         let transaction_deposit = Transaction {
@@ -234,6 +1188,7 @@ mod tests {
             amount: Some(amount_deposit),
             transaction_type: TransactionType::Deposit,
             tx_id: 6,
+            timestamp: None,
         };
 
         processor.process(transaction_deposit);
@@ -243,6 +1198,7 @@ mod tests {
             amount: Some(amount_withdraw),
             transaction_type: TransactionType::Withdrawal,
             tx_id: withdraw_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_withdraw);
@@ -252,6 +1208,7 @@ mod tests {
             amount: None,
             transaction_type: TransactionType::Dispute,
             tx_id: withdraw_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_dispute);
@@ -261,6 +1218,7 @@ mod tests {
             amount: None,
             transaction_type: TransactionType::Resolve,
             tx_id: withdraw_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_resolve);
@@ -268,8 +1226,8 @@ mod tests {
         let accounts = processor.get_accounts();
         let account = accounts.get(&client_id).unwrap();
 
-        assert_eq!(account.get_total(), amount_deposit);
-        assert_eq!(account.get_available(), amount_deposit);
+        assert_eq!(account.get_total_in_cent_parts(), amount_deposit as u64);
+        assert_eq!(account.get_available_in_cent_parts(), amount_deposit as u64);
         assert_eq!(account.get_held(), 0.0);
         assert_eq!(account.is_locked(), false);
     }
@@ -278,7 +1236,7 @@ mod tests {
     fn can_chargeback() {
         let mut processor = PaymentProcessor::new();
         let client_id = 11;
-        let amount_deposit = 25.5;
+        let amount_deposit = 255000;
         let deposit_tx_id = 7;
 
         let transaction_deposit = Transaction {
@@ -286,6 +1244,7 @@ mod tests {
             amount: Some(amount_deposit),
             transaction_type: TransactionType::Deposit,
             tx_id: deposit_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_deposit);
@@ -295,6 +1254,7 @@ mod tests {
             amount: None,
             transaction_type: TransactionType::Dispute,
             tx_id: deposit_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_dispute);
@@ -304,6 +1264,7 @@ mod tests {
             amount: None,
             transaction_type: TransactionType::Chargeback,
             tx_id: deposit_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_chargeback);
@@ -317,12 +1278,44 @@ mod tests {
         assert_eq!(account.is_locked(), true);
     }
 
+    #[test]
+    fn writes_available_net_of_held() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(100000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 1,
+            timestamp: None,
+        });
+
+        let mut output = Vec::new();
+        let mut writer = RecordWriter::new(&mut output, Format::Csv);
+        writer
+            .write(processor.get_accounts().get(&1).unwrap())
+            .unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap().lines().nth(1),
+            Some("1,0.0000,10.0000,10.0000,0.0000,false,active")
+        );
+    }
+
     #[test]
     fn cannot_chargeback_withdraw() {
         let mut processor = PaymentProcessor::new();
         let client_id = 11;
-        let amount_deposit = 25.5;
-        let amount_withdraw = 12.25;
+        let amount_deposit = 255000;
+        let amount_withdraw = 122500;
         let withdraw_tx_id = 7;
 
         let transaction_deposit = Transaction {
@@ -330,6 +1323,7 @@ mod tests {
             amount: Some(amount_deposit),
             transaction_type: TransactionType::Deposit,
             tx_id: 6,
+            timestamp: None,
         };
 
         processor.process(transaction_deposit);
@@ -339,6 +1333,7 @@ mod tests {
             amount: Some(amount_withdraw),
             transaction_type: TransactionType::Withdrawal,
             tx_id: withdraw_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_withdraw);
@@ -348,6 +1343,7 @@ mod tests {
             amount: None,
             transaction_type: TransactionType::Dispute,
             tx_id: withdraw_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_dispute);
@@ -357,6 +1353,7 @@ mod tests {
             amount: None,
             transaction_type: TransactionType::Chargeback,
             tx_id: withdraw_tx_id,
+            timestamp: None,
         };
 
         processor.process(transaction_chargeback);
@@ -364,9 +1361,2714 @@ mod tests {
         let accounts = processor.get_accounts();
         let account = accounts.get(&client_id).unwrap();
 
-        assert_eq!(account.get_total(), amount_deposit - amount_withdraw);
-        assert_eq!(account.get_available(), amount_deposit - amount_withdraw);
-        assert_eq!(account.get_held(), amount_withdraw);
+        assert_eq!(account.get_total_in_cent_parts(), (amount_deposit - amount_withdraw) as u64);
+        assert_eq!(
+            account.get_available_in_cent_parts(),
+            (amount_deposit - amount_withdraw - amount_withdraw) as u64
+        );
+        assert_eq!(account.get_held_in_cent_parts(), amount_withdraw as u64);
         assert_eq!(account.is_locked(), false);
     }
+
+    #[test]
+    fn can_unlock_after_chargeback() {
+        let mut processor = PaymentProcessor::new();
+        let client_id = 12;
+        let deposit_tx_id = 8;
+
+        processor.process(Transaction {
+            client_id,
+            amount: Some(100000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: deposit_tx_id,
+            timestamp: None,
+        });
+
+        processor.process(Transaction {
+            client_id,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: deposit_tx_id,
+            timestamp: None,
+        });
+
+        processor.process(Transaction {
+            client_id,
+            amount: None,
+            transaction_type: TransactionType::Chargeback,
+            tx_id: deposit_tx_id,
+            timestamp: None,
+        });
+
+        processor.process(Transaction {
+            client_id,
+            amount: Some(50000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 9,
+            timestamp: None,
+        });
+
+        let account = processor.get_accounts().get(&client_id).unwrap();
+
+        assert_eq!(account.get_total(), 0.0);
+        assert_eq!(account.get_status(), AccountStatus::Locked);
+
+        let unlock = AdminTransaction {
+            action_type: AdminActionType::Unlock,
+            client_id,
+            reason: ReasonCode::ChargebackReviewed,
+        };
+
+        let account = processor.process_admin(unlock).unwrap();
+
+        assert_eq!(account.get_status(), AccountStatus::Active);
+        assert_eq!(
+            account.get_status_reason(),
+            Some(ReasonCode::ChargebackReviewed)
+        );
+        assert_eq!(account.is_locked(), false);
+
+        processor.process(Transaction {
+            client_id,
+            amount: Some(50000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 10,
+            timestamp: None,
+        });
+
+        let account = processor.get_accounts().get(&client_id).unwrap();
+
+        assert_eq!(account.get_total(), 5.0);
+    }
+
+    #[test]
+    fn cannot_close_account_with_balance() {
+        let mut processor = PaymentProcessor::new();
+        let client_id = 13;
+
+        processor.process(Transaction {
+            client_id,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 11,
+            timestamp: None,
+        });
+
+        let close = AdminTransaction {
+            action_type: AdminActionType::Close,
+            client_id,
+            reason: ReasonCode::CustomerRequest,
+        };
+
+        let result = processor.process_admin(close);
+
+        assert!(matches!(result, Err(OperationError::AccountNotEmpty(13))));
+    }
+
+    #[test]
+    fn rejects_invalid_status_transition() {
+        let mut processor = PaymentProcessor::new();
+
+        let reopen = AdminTransaction {
+            action_type: AdminActionType::Reopen,
+            client_id: 14,
+            reason: ReasonCode::Other,
+        };
+
+        assert!(matches!(
+            processor.process_admin(reopen),
+            Err(OperationError::ClientNotFound(14))
+        ));
+
+        processor.process(Transaction {
+            client_id: 14,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 12,
+            timestamp: None,
+        });
+
+        let reopen = AdminTransaction {
+            action_type: AdminActionType::Reopen,
+            client_id: 14,
+            reason: ReasonCode::Other,
+        };
+
+        assert!(matches!(
+            processor.process_admin(reopen),
+            Err(OperationError::InvalidStatusTransition(
+                14,
+                AdminActionType::Reopen
+            ))
+        ));
+    }
+
+    #[test]
+    fn rejects_withdrawals_over_configured_limits() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [limits]
+            max_withdrawal = 100.0
+            max_daily_withdrawal = 150.0
+
+            [[limits.client]]
+            client = 16
+            max_withdrawal = 10.0
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+
+        for client_id in [15, 16].iter().copied() {
+            processor.process(Transaction {
+                client_id,
+                amount: Some(5000000),
+                transaction_type: TransactionType::Deposit,
+                tx_id: client_id as u32,
+                timestamp: None,
+            });
+        }
+
+        let withdraw = |client_id, amount, tx_id| Transaction {
+            client_id,
+            amount: Some(amount),
+            transaction_type: TransactionType::Withdrawal,
+            tx_id,
+            timestamp: None,
+        };
+
+        assert!(matches!(
+            processor.try_process(withdraw(15, 1010000, 20)),
+            Err(OperationError::LimitExceeded(
+                15,
+                20,
+                LimitKind::SingleWithdrawal
+            ))
+        ));
+        assert!(processor.try_process(withdraw(15, 1000000, 21)).is_ok());
+        assert!(matches!(
+            processor.try_process(withdraw(15, 600000, 22)),
+            Err(OperationError::LimitExceeded(
+                15,
+                22,
+                LimitKind::DailyWithdrawal
+            ))
+        ));
+        assert!(matches!(
+            processor.try_process(withdraw(16, 110000, 23)),
+            Err(OperationError::LimitExceeded(
+                16,
+                23,
+                LimitKind::SingleWithdrawal
+            ))
+        ));
+
+        let accounts = processor.get_accounts();
+
+        assert_eq!(accounts.get(&15).unwrap().get_total(), 400.0);
+        assert_eq!(accounts.get(&16).unwrap().get_total(), 500.0);
+    }
+
+    #[test]
+    fn reads_limits_as_exact_decimals() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [limits]
+            max_withdrawal = 123456789.12
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+
+        processor.process(Transaction {
+            client_id: 27,
+            amount: Some(5_000_000_000_000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: None,
+        });
+
+        assert!(matches!(
+            processor.try_process(Transaction {
+                client_id: 27,
+                amount: Some(1_234_567_915_000),
+                transaction_type: TransactionType::Withdrawal,
+                tx_id: 2,
+                timestamp: None,
+            }),
+            Err(OperationError::LimitExceeded(
+                27,
+                2,
+                LimitKind::SingleWithdrawal
+            ))
+        ));
+        assert!(processor
+            .try_process(Transaction {
+                client_id: 27,
+                amount: Some(1_234_567_891_200),
+                transaction_type: TransactionType::Withdrawal,
+                tx_id: 3,
+                timestamp: None,
+            })
+            .is_ok());
+
+        assert!(PolicyConfig::from_toml("[limits]\nmax_withdrawal = -1.0\n").is_err());
+        assert!(PolicyConfig::from_toml("[limits]\nmax_daily_withdrawal = 0.00001\n").is_err());
+        assert!(PolicyConfig::from_toml("[limits]\nmax_withdrawal = \"10.5\"\n").is_ok());
+    }
+
+    #[test]
+    fn rejects_transactions_over_velocity_limit() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [limits]
+            max_transactions = 2
+            window_seconds = 60
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+        let deposit = |tx_id| Transaction {
+            client_id: 17,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id,
+            timestamp: None,
+        };
+
+        assert!(processor.try_process(deposit(30)).is_ok());
+        assert!(processor.try_process(deposit(31)).is_ok());
+        assert!(matches!(
+            processor.try_process(deposit(32)),
+            Err(OperationError::LimitExceeded(
+                17,
+                32,
+                LimitKind::TransactionCount
+            ))
+        ));
+    }
+
+    #[test]
+    fn freezes_account_on_rapid_withdrawal() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [fraud.rapid_withdrawal]
+            window_seconds = 300
+            action = "freeze_account"
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+
+        processor.process(Transaction {
+            client_id: 18,
+            amount: Some(1000000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 40,
+            timestamp: None,
+        });
+
+        processor.process(Transaction {
+            client_id: 18,
+            amount: Some(950000),
+            transaction_type: TransactionType::Withdrawal,
+            tx_id: 41,
+            timestamp: None,
+        });
+
+        let alerts = processor.take_alerts();
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "rapid_withdrawal");
+        assert_eq!(alerts[0].tx_id, 41);
+
+        let account = processor.get_accounts().get(&18).unwrap();
+
+        assert_eq!(account.get_total(), 5.0);
+        assert_eq!(account.get_status(), AccountStatus::Frozen);
+        assert_eq!(
+            account.get_status_reason(),
+            Some(ReasonCode::FraudInvestigation)
+        );
+    }
+
+    #[test]
+    fn holds_funds_on_rapid_withdrawal() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [fraud.rapid_withdrawal]
+            window_seconds = 300
+            min_ratio = 0.5
+            action = "hold_funds"
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+
+        processor.process(Transaction {
+            client_id: 26,
+            amount: Some(1000000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 42,
+            timestamp: None,
+        });
+
+        processor.process(Transaction {
+            client_id: 26,
+            amount: Some(600000),
+            transaction_type: TransactionType::Withdrawal,
+            tx_id: 43,
+            timestamp: None,
+        });
+
+        assert_eq!(processor.take_alerts()[0].action, FraudAction::HoldFunds);
+
+        let account = processor.get_accounts().get(&26).unwrap();
+
+        assert_eq!(account.get_available(), 0.0);
+        assert_eq!(account.get_held(), 40.0);
+        assert_eq!(account.get_fraud_held_in_cent_parts(), 400000);
+        assert_eq!(account.get_status(), AccountStatus::Active);
+        assert!(matches!(
+            processor.try_process(Transaction {
+                client_id: 26,
+                amount: Some(10000),
+                transaction_type: TransactionType::Withdrawal,
+                tx_id: 44,
+                timestamp: None,
+            }),
+            Err(OperationError::InsufficientBalance(26, 44))
+        ));
+
+        let release = AdminTransaction {
+            action_type: AdminActionType::ReleaseFunds,
+            client_id: 26,
+            reason: ReasonCode::FraudInvestigation,
+        };
+        let account = processor.process_admin(release.clone()).unwrap();
+
+        assert_eq!(account.get_available(), 40.0);
+        assert_eq!(account.get_held(), 0.0);
+        assert!(processor.trial_balance().is_balanced());
+        assert!(matches!(
+            processor.process_admin(release),
+            Err(OperationError::InvalidStatusTransition(
+                26,
+                AdminActionType::ReleaseFunds
+            ))
+        ));
+    }
+
+    #[test]
+    fn locks_account_on_repeated_disputes() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [fraud.repeated_disputes]
+            max_disputes = 1
+            window_seconds = 3600
+            action = "lock_account"
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+
+        for tx_id in 50..52 {
+            processor.process(Transaction {
+                client_id: 19,
+                amount: Some(10000),
+                transaction_type: TransactionType::Deposit,
+                tx_id,
+                timestamp: None,
+            });
+
+            processor.process(Transaction {
+                client_id: 19,
+                amount: None,
+                transaction_type: TransactionType::Dispute,
+                tx_id,
+                timestamp: None,
+            });
+        }
+
+        let alerts = processor.take_alerts();
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].action, FraudAction::LockAccount);
+        assert_eq!(processor.get_accounts().get(&19).unwrap().is_locked(), true);
+    }
+
+    #[test]
+    fn runs_custom_fraud_rule() {
+        struct LargeDeposit;
+
+        impl FraudRule for LargeDeposit {
+            fn name(&self) -> &str {
+                "large_deposit"
+            }
+
+            fn evaluate(&self, context: &RuleContext) -> Option<String> {
+                match context.transaction.amount {
+                    Some(amount) if amount > 10000000 => Some("Large deposit".to_string()),
+                    _ => None,
+                }
+            }
+        }
+
+        let mut processor = PaymentProcessor::new();
+
+        processor.add_rule(LargeDeposit, FraudAction::Log);
+
+        processor.process(Transaction {
+            client_id: 20,
+            amount: Some(50000000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 60,
+            timestamp: None,
+        });
+
+        let alerts = processor.take_alerts();
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "large_deposit");
+        assert_eq!(
+            processor.get_accounts().get(&20).unwrap().get_status(),
+            AccountStatus::Active
+        );
+    }
+
+    #[test]
+    fn parses_optional_timestamp_column() {
+        let input = "type,client,tx,amount,timestamp
+deposit,1,1,1.0,1700000000
+deposit,1,2,1.0,2023-11-14T22:13:21Z
+deposit,1,3,1.0,
+";
+        let mut reader = csv::Reader::from_reader(input.as_bytes());
+        let transactions: Vec<Transaction> = reader
+            .deserialize()
+            .collect::<Result<_, csv::Error>>()
+            .unwrap();
+
+        assert_eq!(transactions[0].timestamp, Some(1_700_000_000));
+        assert_eq!(transactions[1].timestamp, Some(1_700_000_001));
+        assert_eq!(transactions[2].timestamp, None);
+
+        let input = "type,client,tx,amount
+deposit,1,1,1.0
+";
+        let mut reader = csv::Reader::from_reader(input.as_bytes());
+        let transaction: Transaction = reader.deserialize().next().unwrap().unwrap();
+
+        assert_eq!(transaction.timestamp, None);
+    }
+
+    #[test]
+    fn rejects_out_of_order_timestamps() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [timestamps]
+            reorder_window_seconds = 10
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+        let deposit = |tx_id, timestamp| Transaction {
+            client_id: 21,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id,
+            timestamp: Some(timestamp),
+        };
+
+        assert!(processor.try_process(deposit(70, 1000)).is_ok());
+        assert!(processor.try_process(deposit(71, 995)).is_ok());
+        assert!(matches!(
+            processor.try_process(deposit(72, 989)),
+            Err(OperationError::TimestampOutOfOrder(21, 72))
+        ));
+
+        let account = processor.get_accounts().get(&21).unwrap();
+
+        assert_eq!(account.get_last_timestamp(), Some(1000));
+        assert_eq!(
+            account.get_transaction(71).unwrap().transaction.timestamp,
+            Some(995)
+        );
+        assert_eq!(account.get_total(), 2.0);
+    }
+
+    #[test]
+    fn rejects_dispute_outside_window() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [disputes]
+            window_seconds = 100
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+        let deposit = |tx_id, timestamp| Transaction {
+            client_id: 22,
+            amount: Some(50000),
+            transaction_type: TransactionType::Deposit,
+            tx_id,
+            timestamp: Some(timestamp),
+        };
+        let dispute = |tx_id, timestamp| Transaction {
+            client_id: 22,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id,
+            timestamp: Some(timestamp),
+        };
+
+        assert!(processor.try_process(deposit(80, 1000)).is_ok());
+        assert!(processor.try_process(deposit(81, 1050)).is_ok());
+        assert!(matches!(
+            processor.try_process(dispute(80, 1101)),
+            Err(OperationError::DisputeWindowExpired(22, 80))
+        ));
+        assert!(processor.try_process(dispute(81, 1101)).is_ok());
+
+        let account = processor.get_accounts().get(&22).unwrap();
+
+        assert_eq!(account.get_held(), 5.0);
+    }
+
+    #[test]
+    fn expires_open_disputes_after_deadline() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [disputes]
+            expiry_seconds = 100
+            on_expiry = "chargeback"
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+        let deposit = |client_id, tx_id, timestamp| Transaction {
+            client_id,
+            amount: Some(50000),
+            transaction_type: TransactionType::Deposit,
+            tx_id,
+            timestamp: Some(timestamp),
+        };
+
+        processor.process(deposit(23, 90, 1000));
+        processor.process(Transaction {
+            client_id: 23,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 90,
+            timestamp: Some(1010),
+        });
+        processor.process(deposit(24, 91, 1020));
+        processor.process(Transaction {
+            client_id: 24,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 91,
+            timestamp: Some(1030),
+        });
+        processor.process(Transaction {
+            client_id: 24,
+            amount: None,
+            transaction_type: TransactionType::Resolve,
+            tx_id: 91,
+            timestamp: Some(1040),
+        });
+
+        assert!(processor.take_expired_disputes().is_empty());
+
+        processor.process(deposit(25, 92, 1200));
+
+        let expired = processor.take_expired_disputes();
+
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].tx_id, 90);
+        assert_eq!(expired[0].action, ExpiryAction::Chargeback);
+        assert_eq!(expired[0].deadline, 1110);
+
+        let account = processor.get_accounts().get(&23).unwrap();
+
+        assert_eq!(account.get_total(), 0.0);
+        assert_eq!(account.get_held(), 0.0);
+        assert_eq!(account.is_locked(), true);
+        assert_eq!(
+            account.get_transaction(90).unwrap().dispute_status,
+            DisputeStatus::ChargedBack
+        );
+    }
+
+    #[test]
+    fn rejected_rows_do_not_expire_disputes() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [disputes]
+            expiry_seconds = 100
+            on_expiry = "chargeback"
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(50000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: Some(10),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 1,
+            timestamp: Some(20),
+        });
+
+        assert!(matches!(
+            processor.try_process(Transaction {
+                client_id: 2,
+                amount: Some(-10000),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 2,
+                timestamp: Some(999999),
+            }),
+            Err(OperationError::NegativeAmount(2, 2))
+        ));
+        assert!(processor.take_expired_disputes().is_empty());
+
+        let account = processor.get_accounts().get(&1).unwrap();
+
+        assert_eq!(account.get_held(), 5.0);
+        assert_eq!(account.is_locked(), false);
+        assert_eq!(
+            account.get_transaction(1).unwrap().dispute_status,
+            DisputeStatus::Disputed
+        );
+    }
+
+    #[test]
+    fn parses_input_without_subcommand() {
+        let cli = Cli::try_parse_from(["rust_test", "transactions.csv"]).unwrap();
+
+        assert!(cli.command.is_none());
+        assert_eq!(
+            cli.options.input_paths[0].to_str(),
+            Some("transactions.csv")
+        );
+
+        let cli = Cli::try_parse_from([
+            "rust_test",
+            "replay",
+            "--input",
+            "transactions.csv",
+            "--strict",
+        ])
+        .unwrap();
+
+        assert!(matches!(cli.command, Some(Command::Replay(options)) if options.strict));
+    }
+
+    #[test]
+    fn strict_run_stops_before_any_output() {
+        let path = std::env::temp_dir().join("rust_test_strict_run_stops_before_any_output.csv");
+
+        std::fs::write(
+            &path,
+            "type,client,tx,amount\ndeposit,1,1,10.0\nwithdrawal,1,2,50.0\n",
+        )
+        .unwrap();
+
+        let cli = Cli::try_parse_from(["rust_test", path.to_str().unwrap(), "--strict"]).unwrap();
+        let mut processor = PaymentProcessor::new();
+        let mut processed = 0;
+        let result = super::run(&cli.options, &mut processor, &mut std::io::sink(), |_| {
+            processed += 1;
+            Ok(())
+        });
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(e) if e.is::<super::RolledBack>()));
+        assert_eq!(processed, 0);
+        assert!(processor.get_accounts().is_empty());
+    }
+
+    #[test]
+    fn strict_replay_leaves_output_untouched() {
+        let directory = std::env::temp_dir();
+        let input = directory.join("rust_test_strict_replay_input.csv");
+        let output = directory.join("rust_test_strict_replay_output.csv");
+
+        std::fs::write(
+            &input,
+            "type,client,tx,amount\ndeposit,1,1,10.0\nwithdrawal,1,2,50.0\n",
+        )
+        .unwrap();
+        std::fs::write(&output, "previous\n").unwrap();
+
+        let cli = Cli::try_parse_from([
+            "rust_test",
+            "replay",
+            input.to_str().unwrap(),
+            "--strict",
+            "--output",
+            output.to_str().unwrap(),
+        ])
+        .unwrap();
+        let result = match cli.command {
+            Some(Command::Replay(options)) => super::replay(&options),
+            _ => unreachable!(),
+        };
+        let written = std::fs::read_to_string(&output).unwrap();
+
+        for path in [&input, &output].iter() {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert!(matches!(result, Err(e) if e.is::<super::RolledBack>()));
+        assert_eq!(written, "previous\n");
+    }
+
+    #[test]
+    fn keeps_run_errors_in_errors_file() {
+        let directory = std::env::temp_dir();
+        let input = directory.join("rust_test_keeps_run_errors_input.csv");
+        let expected = directory.join("rust_test_keeps_run_errors_expected.csv");
+        let output = directory.join("rust_test_keeps_run_errors_output.csv");
+        let errors = directory.join("rust_test_keeps_run_errors_errors.txt");
+
+        std::fs::write(
+            &input,
+            "type,client,tx,amount\ndeposit,1,1,1677.7217\nwithdrawal,1,2,5000.0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &expected,
+            "client,available,held,total,locked\n1,1677.7217,0,1677.7217,false\n",
+        )
+        .unwrap();
+
+        let arguments = |command: &str| {
+            let mut arguments = vec!["rust_test", command, input.to_str().unwrap()];
+
+            if command == "reconcile" {
+                arguments.extend(&["--expected", expected.to_str().unwrap(), "--only-locked"]);
+            }
+
+            arguments.extend(&[
+                "--output",
+                output.to_str().unwrap(),
+                "--errors",
+                errors.to_str().unwrap(),
+            ]);
+            Cli::try_parse_from(arguments).unwrap()
+        };
+
+        match arguments("reconcile").command {
+            Some(Command::Reconcile(options)) => super::reconcile(&options).unwrap(),
+            _ => unreachable!(),
+        };
+
+        let reconcile_errors = std::fs::read_to_string(&errors).unwrap();
+
+        match arguments("trial-balance").command {
+            Some(Command::TrialBalance(options)) => super::trial_balance(&options).unwrap(),
+            _ => unreachable!(),
+        };
+
+        let trial_balance_errors = std::fs::read_to_string(&errors).unwrap();
+
+        for path in [&input, &expected, &output, &errors].iter() {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert!(reconcile_errors.contains("Transaction error occured"));
+        assert!(reconcile_errors.contains("Reconciliation: 1 expected clients, 0 breaks"));
+        assert!(trial_balance_errors.contains("Transaction error occured"));
+        assert!(trial_balance_errors.contains("Trial balance:"));
+    }
+
+    #[test]
+    fn rolls_back_rejected_batch() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(100000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: None,
+        });
+
+        let result = processor.process_batch(vec![
+            Transaction {
+                client_id: 1,
+                amount: Some(40000),
+                transaction_type: TransactionType::Withdrawal,
+                tx_id: 2,
+                timestamp: None,
+            },
+            Transaction {
+                client_id: 2,
+                amount: Some(50000),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 3,
+                timestamp: None,
+            },
+            Transaction {
+                client_id: 1,
+                amount: None,
+                transaction_type: TransactionType::Dispute,
+                tx_id: 2,
+                timestamp: None,
+            },
+            Transaction {
+                client_id: 2,
+                amount: Some(60000),
+                transaction_type: TransactionType::Withdrawal,
+                tx_id: 4,
+                timestamp: None,
+            },
+        ]);
+
+        let error = result.unwrap_err();
+
+        assert_eq!(error.index, 3);
+        assert!(matches!(
+            error.error,
+            OperationError::InsufficientBalance(2, 4)
+        ));
+
+        let accounts = processor.get_accounts();
+        let account = accounts.get(&1).unwrap();
+
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(account.get_total(), 10.0);
+        assert_eq!(account.get_held(), 0.0);
+        assert!(account.get_transaction(2).is_none());
+
+        let applied = processor
+            .process_batch(vec![
+                Transaction {
+                    client_id: 1,
+                    amount: Some(40000),
+                    transaction_type: TransactionType::Withdrawal,
+                    tx_id: 2,
+                    timestamp: None,
+                },
+                Transaction {
+                    client_id: 2,
+                    amount: Some(50000),
+                    transaction_type: TransactionType::Deposit,
+                    tx_id: 3,
+                    timestamp: None,
+                },
+            ])
+            .unwrap();
+
+        assert_eq!(applied, 2);
+        assert_eq!(processor.get_accounts().get(&1).unwrap().get_total(), 6.0);
+        assert_eq!(processor.get_accounts().get(&2).unwrap().get_total(), 5.0);
+    }
+
+    #[test]
+    fn dry_run_leaves_processor_untouched() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(100000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: None,
+        });
+
+        let dry_run = processor.dry_run(vec![
+            Transaction {
+                client_id: 1,
+                amount: Some(45000),
+                transaction_type: TransactionType::Withdrawal,
+                tx_id: 2,
+                timestamp: None,
+            },
+            Transaction {
+                client_id: 2,
+                amount: Some(50000),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 3,
+                timestamp: None,
+            },
+            Transaction {
+                client_id: 2,
+                amount: Some(60000),
+                transaction_type: TransactionType::Withdrawal,
+                tx_id: 4,
+                timestamp: None,
+            },
+            Transaction {
+                client_id: 2,
+                amount: None,
+                transaction_type: TransactionType::Dispute,
+                tx_id: 9,
+                timestamp: None,
+            },
+        ]);
+        let summary = &dry_run.summary;
+
+        assert_eq!(dry_run.outcomes.len(), 4);
+        assert_eq!(dry_run.outcomes[0].is_accepted(), true);
+        assert_eq!(dry_run.outcomes[2].is_accepted(), false);
+        assert_eq!(summary.rows, 4);
+        assert_eq!(summary.accepted, 2);
+        assert_eq!(summary.rejected, 2);
+        assert_eq!(summary.by_type["withdrawal"].accepted, 1);
+        assert_eq!(summary.by_type["withdrawal"].rejected, 1);
+        assert_eq!(summary.rejects["insufficient_balance"], 1);
+        assert_eq!(summary.rejects["transaction_not_found"], 1);
+        assert_eq!(summary.balance_changes[&1], -45000);
+        assert_eq!(summary.balance_changes[&2], 50000);
+
+        let mut output = Vec::new();
+        let mut writer = RecordWriter::new(&mut output, Format::Csv);
+        writer.write(&dry_run.outcomes[0]).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap().lines().nth(1),
+            Some("1,withdrawal,1,2,4.5000,,accepted,")
+        );
+
+        let accounts = processor.get_accounts();
+
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts.get(&1).unwrap().get_total(), 10.0);
+    }
+
+    #[test]
+    fn maintains_processing_stats() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(100000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(25000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 2,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(10000),
+            transaction_type: TransactionType::Withdrawal,
+            tx_id: 3,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: Some(10000),
+            transaction_type: TransactionType::Withdrawal,
+            tx_id: 4,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 2,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Chargeback,
+            tx_id: 2,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 3,
+            timestamp: None,
+        });
+        processor.record_parse_error();
+
+        let stats = processor.stats();
+
+        assert_eq!(stats.rows_read, 8);
+        assert_eq!(stats.rows_parsed, 7);
+        assert_eq!(stats.accepted, 6);
+        assert_eq!(stats.rejected, 1);
+        assert_eq!(stats.by_type["withdrawal"].accepted, 1);
+        assert_eq!(stats.by_type["withdrawal"].rejected, 1);
+        assert_eq!(stats.rejects["insufficient_balance"], 1);
+        assert_eq!(stats.deposited, 125000);
+        assert_eq!(stats.withdrawn, 10000);
+        assert_eq!(stats.charged_back, 25000);
+        assert_eq!(stats.held, 10000);
+        assert_eq!(stats.accounts, 2);
+        assert_eq!(stats.locked_accounts, 1);
+
+        let json = serde_json::to_value(&stats).unwrap();
+
+        assert_eq!(json["deposited"], "12.5000");
+    }
+
+    #[test]
+    fn writes_client_statement() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(100000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: Some(100),
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: Some(30000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 2,
+            timestamp: Some(150),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(25000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 3,
+            timestamp: Some(200),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(200000),
+            transaction_type: TransactionType::Withdrawal,
+            tx_id: 4,
+            timestamp: Some(250),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 3,
+            timestamp: Some(300),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Resolve,
+            tx_id: 3,
+            timestamp: Some(400),
+        });
+
+        let statement = processor.statement(1, None, None).unwrap();
+        let sequences: Vec<u64> = statement
+            .entries
+            .iter()
+            .map(|entry| entry.sequence)
+            .collect();
+
+        assert_eq!(sequences, vec![1, 3, 5, 6]);
+        assert_eq!(statement.entries[1].total, 125000);
+        assert_eq!(
+            statement.entries[2].transaction_type,
+            TransactionType::Dispute
+        );
+        assert_eq!(statement.entries[2].amount, 25000);
+        assert_eq!(statement.entries[2].held, 25000);
+        assert_eq!(statement.entries[2].dispute_status, DisputeStatus::Disputed);
+        assert_eq!(statement.entries[3].held, 0);
+        assert_eq!(statement.entries[3].dispute_status, DisputeStatus::Resolved);
+
+        let statement = processor.statement(1, Some(200), Some(300)).unwrap();
+
+        assert_eq!(statement.entries.len(), 2);
+        assert!(statement.to_string().contains("Statement for client 1"));
+        assert!(processor.statement(3, None, None).is_none());
+    }
+
+    #[test]
+    fn answers_balance_as_of() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(100000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: Some(100),
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: Some(30000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 2,
+            timestamp: Some(150),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(40000),
+            transaction_type: TransactionType::Withdrawal,
+            tx_id: 3,
+            timestamp: Some(200),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 3,
+            timestamp: Some(300),
+        });
+
+        let balance = |as_of| processor.balance_as_of(1, as_of).unwrap();
+
+        assert_eq!(balance(AsOf::Sequence(2)).total, 100000);
+        assert_eq!(balance(AsOf::Sequence(2)).sequence, Some(1));
+        assert_eq!(balance(AsOf::Sequence(3)).total, 60000);
+        assert_eq!(balance(AsOf::Timestamp(99)).total, 0);
+        assert_eq!(balance(AsOf::Timestamp(99)).sequence, None);
+        assert_eq!(balance(AsOf::Timestamp(299)).held, 0);
+        assert_eq!(balance(AsOf::Timestamp(300)).held, 40000);
+        assert_eq!(balance(AsOf::Transaction(1)).total, 100000);
+        assert!(processor.balance_as_of(1, AsOf::Transaction(2)).is_none());
+        assert!(processor.balance_as_of(3, AsOf::Sequence(1)).is_none());
+    }
+
+    #[test]
+    fn answers_balance_as_of_time_past_untimed_movements() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: Some(100),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 2,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 3,
+            timestamp: Some(200),
+        });
+
+        let balance = processor.balance_as_of(1, AsOf::Timestamp(250)).unwrap();
+
+        assert_eq!(balance.total, 30000);
+        assert_eq!(balance.sequence, Some(3));
+        assert_eq!(
+            processor
+                .balance_as_of(1, AsOf::Timestamp(150))
+                .unwrap()
+                .total,
+            10000
+        );
+    }
+
+    #[test]
+    fn answers_balance_as_of_time_past_reordered_movements() {
+        let policy = PolicyConfig::from_toml(
+            r#"
+            [timestamps]
+            reorder_window_seconds = 10
+            "#,
+        )
+        .unwrap();
+        let mut processor = PaymentProcessor::with_policy(policy);
+
+        for (tx_id, amount, timestamp) in [(1, 100000, 100), (2, 50000, 120), (3, 10000, 115)]
+            .iter()
+            .copied()
+        {
+            processor.process(Transaction {
+                client_id: 1,
+                amount: Some(amount),
+                transaction_type: TransactionType::Deposit,
+                tx_id,
+                timestamp: Some(timestamp),
+            });
+        }
+
+        let balance = processor.balance_as_of(1, AsOf::Timestamp(117)).unwrap();
+
+        assert_eq!(balance.total, 110000);
+        assert_eq!(balance.available, 110000);
+        assert_eq!(balance.sequence, Some(3));
+        assert_eq!(balance.timestamp, Some(115));
+        assert_eq!(
+            processor
+                .balance_as_of(1, AsOf::Timestamp(120))
+                .unwrap()
+                .total,
+            160000
+        );
+    }
+
+    #[test]
+    fn reconciles_expected_balances() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(100000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: Some(30000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 2,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 4,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 3,
+            timestamp: None,
+        });
+
+        let expected = ExpectedBalance::read_csv(
+            "client, available, held, total, locked
+1, 10, 0.0, 10.0000, false
+2, 3.0001, 0, 3.0001, true
+3, 0, 0, 0, false
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        let breaks = reconcile::reconcile(&expected, processor.get_accounts().values());
+        let summary: Vec<(u16, BreakKind, Option<&str>, Option<&str>)> = breaks
+            .iter()
+            .map(|b| (b.client, b.kind, b.field, b.delta.as_deref()))
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (2, BreakKind::Mismatch, Some("available"), Some("-0.0001")),
+                (2, BreakKind::Mismatch, Some("total"), Some("-0.0001")),
+                (2, BreakKind::Mismatch, Some("locked"), None),
+                (3, BreakKind::Missing, None, None),
+                (4, BreakKind::Extra, None, None),
+            ]
+        );
+
+        assert!(ExpectedBalance::read_csv(
+            "client,available,held,total,locked\n1,0.00001,0,0,false\n".as_bytes()
+        )
+        .is_err());
+        assert!(ExpectedBalance::read_csv(
+            "client,available,held,total,locked\n1,0,0,0,false\n1,0,0,0,false\n".as_bytes()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reports_open_disputes_by_age() {
+        let mut processor = PaymentProcessor::new();
+        let day = 86_400;
+
+        processor.process(Transaction {
+            client_id: 2,
+            amount: Some(40000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: Some(0),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(15000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 2,
+            timestamp: Some(0),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(20000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 3,
+            timestamp: Some(0),
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 1,
+            timestamp: Some(day),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 2,
+            timestamp: Some(day),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Resolve,
+            tx_id: 2,
+            timestamp: Some(day),
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 3,
+            timestamp: None,
+        });
+
+        let open_disputes = processor.open_disputes(10 * day);
+
+        assert_eq!(open_disputes.len(), 2);
+        assert_eq!(open_disputes[0].client_id, 1);
+        assert_eq!(open_disputes[0].tx_id, 3);
+        assert_eq!(open_disputes[0].bucket, AgingBucket::Unknown);
+        assert_eq!(open_disputes[1].client_id, 2);
+        assert_eq!(open_disputes[1].amount, 40000);
+        assert_eq!(open_disputes[1].age_seconds, Some(9 * day));
+        assert_eq!(open_disputes[1].bucket, AgingBucket::UnderMonth);
+
+        let totals = disputes::aging_totals(&open_disputes);
+        let total = totals.last().unwrap();
+
+        assert_eq!(totals.len(), 6);
+        assert_eq!(total.bucket, None);
+        assert_eq!(total.count, 2);
+        assert_eq!(total.amount, 60000);
+    }
+
+    #[test]
+    fn saturates_aging_totals_of_large_disputes() {
+        let mut processor = PaymentProcessor::new();
+
+        for client_id in 1..4 {
+            processor.process(Transaction {
+                client_id,
+                amount: Some(i64::MAX),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 1,
+                timestamp: None,
+            });
+            processor.process(Transaction {
+                client_id,
+                amount: None,
+                transaction_type: TransactionType::Dispute,
+                tx_id: 1,
+                timestamp: None,
+            });
+        }
+
+        let totals = disputes::aging_totals(&processor.open_disputes(0));
+        let total = totals.last().unwrap();
+
+        assert_eq!(total.count, 3);
+        assert_eq!(total.amount, u64::MAX);
+    }
+
+    #[test]
+    fn keeps_ledger_balanced() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(100000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(25000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 2,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(15000),
+            transaction_type: TransactionType::Withdrawal,
+            tx_id: 3,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 1,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Resolve,
+            tx_id: 1,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 2,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: Some(40000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 4,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 4,
+            timestamp: None,
+        });
+
+        let ledger = processor.get_ledger();
+        let trial_balance = processor.trial_balance();
+        let account = &processor.get_accounts()[&1];
+
+        assert!(trial_balance.is_balanced());
+        assert_eq!(ledger.entries().len(), 8);
+        assert_eq!(ledger.entries()[2].transaction_type, TransactionType::Withdrawal);
+        assert_eq!(
+            ledger.entries()[2].change_of(LedgerAccount::Settlement),
+            15000
+        );
+        assert!(ledger.entries().iter().all(JournalEntry::is_balanced));
+        assert_eq!(ledger.balance(LedgerAccount::Settlement), -150000);
+        assert_eq!(
+            ledger.balance(LedgerAccount::ClientHeld(1)),
+            account.get_held_in_cent_parts() as i128
+        );
+        assert_eq!(
+            ledger.balance(LedgerAccount::ClientAvailable(1))
+                + ledger.balance(LedgerAccount::ClientHeld(1)),
+            account.get_total_in_cent_parts() as i128
+        );
+        assert!(Account::new(3)
+            .handle(Transaction {
+                client_id: 3,
+                amount: Some(10000),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 5,
+                timestamp: None,
+            })
+            .unwrap()
+            .is_balanced());
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Chargeback,
+            tx_id: 2,
+            timestamp: None,
+        });
+
+        let mut trial_balance = processor.trial_balance();
+        let held = trial_balance
+            .rows
+            .iter()
+            .position(|row| row.account == LedgerAccount::ClientHeld(2))
+            .unwrap();
+
+        assert!(trial_balance.is_balanced());
+        assert_eq!(trial_balance.rows[held].expected, Some(40000));
+
+        trial_balance.rows[held].expected = Some(0);
+
+        assert!(!trial_balance.is_balanced());
+        assert_eq!(trial_balance.differences().count(), 1);
+        assert_eq!(
+            processor
+                .get_ledger()
+                .balance(LedgerAccount::ChargebackLoss),
+            25000
+        );
+    }
+
+    #[test]
+    fn verifies_audit_log_chain() {
+        let mut processor = PaymentProcessor::new();
+        let mut log = AuditLog::new(Vec::new());
+        let transactions = vec![
+            (TransactionType::Deposit, 1, Some(20000)),
+            (TransactionType::Withdrawal, 2, Some(50000)),
+            (TransactionType::Withdrawal, 3, Some(15000)),
+            (TransactionType::Deposit, 4, Some(-50000)),
+            (TransactionType::Deposit, 5, None),
+        ];
+
+        for (sequence, (transaction_type, tx_id, amount)) in transactions.into_iter().enumerate() {
+            let transaction = Transaction {
+                client_id: 1,
+                amount,
+                transaction_type,
+                tx_id,
+                timestamp: None,
+            };
+
+            log.append(&ProcessedTransaction {
+                sequence: sequence as u64 + 1,
+                error: processor.try_process(transaction.clone()).err(),
+                transaction,
+            })
+            .unwrap();
+        }
+
+        let text = String::from_utf8(log.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(audit::verify(text.as_bytes()), Ok(5));
+        assert!(lines[0].contains(audit::GENESIS_HASH));
+        assert!(lines[1].contains("\"error\":\"insufficient_balance\""));
+        assert!(lines[3].contains("\"amount\":\"-5.0000\""));
+        assert!(lines[4].contains("\"amount\":null"));
+
+        let tampered = text.replace("\"amount\":\"1.5000\"", "\"amount\":\"0.5000\"");
+        let broken = audit::verify(tampered.as_bytes()).unwrap_err();
+
+        assert_eq!(broken.line, 3);
+        assert_eq!(broken.sequence, Some(3));
+        assert_eq!(broken.reason, "hash does not match the record content");
+
+        let removed = format!("{}\n{}\n", lines[0], lines[2]);
+        let broken = audit::verify(removed.as_bytes()).unwrap_err();
+
+        assert_eq!(broken.line, 2);
+        assert_eq!(
+            broken.reason,
+            "previous hash does not match the record before"
+        );
+    }
+
+    #[test]
+    fn appends_to_existing_audit_log() {
+        let path = std::env::temp_dir().join("rust_test_appends_to_existing_audit_log.ndjson");
+        let _ = std::fs::remove_file(&path);
+        let cli =
+            Cli::try_parse_from(["rust_test", "in.csv", "--audit", path.to_str().unwrap()])
+                .unwrap();
+
+        for tx_id in 1..=2 {
+            let mut log = super::open_audit(&cli.options).unwrap().unwrap();
+
+            log.append(&ProcessedTransaction {
+                sequence: 1,
+                transaction: Transaction {
+                    client_id: 1,
+                    amount: Some(10000),
+                    transaction_type: TransactionType::Deposit,
+                    tx_id,
+                    timestamp: None,
+                },
+                error: None,
+            })
+            .unwrap();
+            log.finish().unwrap();
+        }
+
+        let text = std::fs::read_to_string(&path).unwrap();
+
+        assert_eq!(audit::verify(text.as_bytes()), Ok(2));
+
+        std::fs::write(&path, text.replace("\"tx\":1", "\"tx\":3")).unwrap();
+        let reopened = super::open_audit(&cli.options);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(reopened.is_err());
+    }
+
+    #[test]
+    fn reports_invariant_violations() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.set_check_invariants(true);
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(30000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 1,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Resolve,
+            tx_id: 1,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(50000),
+            transaction_type: TransactionType::Withdrawal,
+            tx_id: 2,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 3,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 3,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: None,
+            transaction_type: TransactionType::Chargeback,
+            tx_id: 3,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 2,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 4,
+            timestamp: None,
+        });
+
+        assert!(processor.take_violations().is_empty());
+
+        let locked = &processor.get_accounts()[&2];
+        let deposit = Transaction {
+            client_id: 2,
+            amount: Some(10000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 5,
+            timestamp: None,
+        };
+        let mut changed = Account::new(2);
+
+        changed.handle(deposit.clone()).unwrap();
+
+        let violations =
+            invariants::check(&AccountSnapshot::of(locked), &changed, &deposit, None);
+
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].invariant, Invariant::LockedUnchanged);
+        assert_eq!(
+            violations[0].message,
+            "locked account changed from held 0 total 0 to held 0 total 10000"
+        );
+        assert_eq!(violations[1].invariant, Invariant::NoUnderflow);
+        assert_eq!(violations[1].transaction.tx_id, 5);
+    }
+
+    #[test]
+    fn rejects_duplicate_transaction_ids() {
+        let mut processor = PaymentProcessor::new();
+
+        processor.process(Transaction {
+            client_id: 1,
+            amount: Some(100000),
+            transaction_type: TransactionType::Deposit,
+            tx_id: 1,
+            timestamp: None,
+        });
+        processor.process(Transaction {
+            client_id: 1,
+            amount: None,
+            transaction_type: TransactionType::Dispute,
+            tx_id: 1,
+            timestamp: None,
+        });
+
+        assert!(matches!(
+            processor.try_process(Transaction {
+                client_id: 1,
+                amount: Some(50000),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 1,
+                timestamp: None,
+            }),
+            Err(OperationError::DuplicateTransaction(1, 1))
+        ));
+        assert!(matches!(
+            processor.try_process(Transaction {
+                client_id: 1,
+                amount: Some(50000),
+                transaction_type: TransactionType::Withdrawal,
+                tx_id: 1,
+                timestamp: None,
+            }),
+            Err(OperationError::DuplicateTransaction(1, 1))
+        ));
+        assert!(processor
+            .try_process(Transaction {
+                client_id: 1,
+                amount: None,
+                transaction_type: TransactionType::Resolve,
+                tx_id: 1,
+                timestamp: None,
+            })
+            .is_ok());
+
+        let account = &processor.get_accounts()[&1];
+
+        assert_eq!(account.get_total(), 10.0);
+        assert_eq!(account.get_held(), 0.0);
+    }
+
+    #[test]
+    fn disputes_withdrawn_funds_with_a_shortfall() {
+        let mut processor = PaymentProcessor::new();
+
+        for transaction in [
+            Transaction {
+                client_id: 1,
+                amount: Some(100000),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 1,
+                timestamp: None,
+            },
+            Transaction {
+                client_id: 1,
+                amount: Some(60000),
+                transaction_type: TransactionType::Withdrawal,
+                tx_id: 2,
+                timestamp: None,
+            },
+            Transaction {
+                client_id: 1,
+                amount: None,
+                transaction_type: TransactionType::Dispute,
+                tx_id: 1,
+                timestamp: None,
+            },
+        ] {
+            assert!(processor.try_process(transaction).is_ok());
+        }
+
+        let account = &processor.get_accounts()[&1];
+
+        assert_eq!(account.get_available_in_cent_parts(), 0);
+        assert_eq!(account.get_held_in_cent_parts(), 40000);
+        assert_eq!(account.get_total_in_cent_parts(), 40000);
+        assert_eq!(account.get_transaction(1).unwrap().shortfall, 60000);
+
+        assert!(processor
+            .try_process(Transaction {
+                client_id: 1,
+                amount: None,
+                transaction_type: TransactionType::Chargeback,
+                tx_id: 1,
+                timestamp: None,
+            })
+            .is_ok());
+
+        let account = &processor.get_accounts()[&1];
+        let trial_balance = processor.trial_balance();
+
+        assert_eq!(account.get_total_in_cent_parts(), 0);
+        assert_eq!(account.get_held_in_cent_parts(), 0);
+        assert_eq!(account.get_owed_in_cent_parts(), 60000);
+        assert!(account.is_locked());
+        assert!(trial_balance.is_balanced());
+        assert_eq!(
+            processor
+                .get_ledger()
+                .balance(LedgerAccount::ClientReceivable(1)),
+            -60000
+        );
+        assert_eq!(
+            processor.get_ledger().balance(LedgerAccount::ChargebackLoss),
+            100000
+        );
+        assert_eq!(account.to_string(), "1,0.0000,0.0000,0.0000,6.0000,true,locked");
+
+        let expected = ExpectedBalance::read_csv(
+            "client,available,held,total,locked\n1,0,0,0,true\n".as_bytes(),
+        )
+        .unwrap();
+        let breaks = reconcile::reconcile(&expected, processor.get_accounts().values());
+
+        assert_eq!(breaks.len(), 1);
+        assert_eq!(breaks[0].field, Some("owed"));
+        assert_eq!(breaks[0].delta.as_deref(), Some("+6.0000"));
+
+        let unlock = AdminTransaction {
+            action_type: AdminActionType::Unlock,
+            client_id: 1,
+            reason: ReasonCode::ChargebackReviewed,
+        };
+        let close = AdminTransaction {
+            action_type: AdminActionType::Close,
+            client_id: 1,
+            reason: ReasonCode::CustomerRequest,
+        };
+
+        assert!(processor.process_admin(unlock).is_ok());
+        assert!(matches!(
+            processor.process_admin(close),
+            Err(OperationError::AccountNotEmpty(1))
+        ));
+    }
+
+    #[test]
+    fn rejects_amounts_that_overflow_the_balance() {
+        let mut processor = PaymentProcessor::new();
+
+        assert!(processor
+            .try_process(Transaction {
+                client_id: 1,
+                amount: Some(i64::MAX),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 1,
+                timestamp: None,
+            })
+            .is_ok());
+
+        let deposited = processor.get_accounts()[&1].get_total_in_cent_parts();
+
+        assert!(processor
+            .try_process(Transaction {
+                client_id: 1,
+                amount: Some(i64::MAX),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 2,
+                timestamp: None,
+            })
+            .is_ok());
+        assert!(matches!(
+            processor.try_process(Transaction {
+                client_id: 1,
+                amount: Some(i64::MAX),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 3,
+                timestamp: None,
+            }),
+            Err(OperationError::BalanceOverflow(1, 3))
+        ));
+        assert!(matches!(
+            processor.try_process(Transaction {
+                client_id: 1,
+                amount: Some(2),
+                transaction_type: TransactionType::Deposit,
+                tx_id: 4,
+                timestamp: None,
+            }),
+            Err(OperationError::BalanceOverflow(1, 4))
+        ));
+        assert!(matches!(
+            processor.try_process(Transaction {
+                client_id: 1,
+                amount: Some(-10000),
+                transaction_type: TransactionType::Withdrawal,
+                tx_id: 5,
+                timestamp: None,
+            }),
+            Err(OperationError::NegativeAmount(1, 5))
+        ));
+        assert!(matches!(
+            processor.try_process(Transaction {
+                client_id: 1,
+                amount: None,
+                transaction_type: TransactionType::Deposit,
+                tx_id: 6,
+                timestamp: None,
+            }),
+            Err(OperationError::InvalidData(1, 6))
+        ));
+
+        let account = &processor.get_accounts()[&1];
+
+        assert_eq!(account.get_total_in_cent_parts(), 2 * deposited);
+        assert!(processor.trial_balance().is_balanced());
+    }
+
+    fn any_amount() -> impl Strategy<Value = i64> {
+        prop_oneof![
+            any::<i64>(),
+            0i64..10000000,
+            Just(i64::MAX),
+            Just(i64::MIN),
+        ]
+    }
+
+    fn any_transaction() -> impl Strategy<Value = Transaction> {
+        (
+            0..5u8,
+            0..3u16,
+            0..8u32,
+            proptest::option::of(any_amount()),
+            proptest::option::weighted(0.2, any::<u64>()),
+        )
+            .prop_map(|(transaction_type, client_id, tx_id, amount, timestamp)| Transaction {
+                transaction_type: match transaction_type {
+                    0 => TransactionType::Deposit,
+                    1 => TransactionType::Withdrawal,
+                    2 => TransactionType::Dispute,
+                    3 => TransactionType::Resolve,
+                    _ => TransactionType::Chargeback,
+                },
+                client_id,
+                tx_id,
+                amount,
+                timestamp,
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics_or_wraps(transactions in proptest::collection::vec(any_transaction(), 0..40)) {
+            let mut processor = PaymentProcessor::new();
+
+            processor.set_check_invariants(true);
+
+            for transaction in transactions {
+                let negative = transaction.amount.is_some_and(|amount| amount < 0);
+                let moves_funds = matches!(
+                    transaction.transaction_type,
+                    TransactionType::Deposit | TransactionType::Withdrawal
+                );
+                let result = processor.try_process(transaction);
+
+                if negative && moves_funds {
+                    prop_assert!(result.is_err());
+                }
+            }
+
+            let violations = processor.take_violations();
+
+            prop_assert!(violations.is_empty(), "{:?}", violations);
+            prop_assert!(processor.trial_balance().is_balanced());
+        }
+    }
+
+    /// Row of a generated sequence, the amount is in cent parts like the
+    /// amount of a transaction.
+    #[derive(Debug, Clone, Copy)]
+    struct ModelOp {
+        transaction_type: TransactionType,
+        client_id: u16,
+        tx_id: u32,
+        amount: Option<i64>,
+    }
+
+    impl ModelOp {
+        fn transaction(&self) -> Transaction {
+            Transaction {
+                client_id: self.client_id,
+                amount: self.amount,
+                transaction_type: self.transaction_type,
+                tx_id: self.tx_id,
+                timestamp: None,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    struct ModelRecord {
+        withdrawal: bool,
+        amount: i64,
+        held: i64,
+        disputed: bool,
+        charged_back: bool,
+    }
+
+    /// Reference implementation of the account rules, written from the rules
+    /// in the readme rather than from `Account`. It keeps the available and
+    /// held balances, the total is their sum. Withdrawals take from the
+    /// available balance, a dispute holds as much of the amount as is
+    /// available and a chargeback leaves the rest owed. Transaction ids are
+    /// unique per account.
+    #[derive(Debug, Default)]
+    struct ModelAccount {
+        available: i64,
+        held: i64,
+        owed: i64,
+        locked: bool,
+        records: BTreeMap<u32, ModelRecord>,
+    }
+
+    impl ModelAccount {
+        fn total(&self) -> i64 {
+            self.available + self.held
+        }
+
+        fn apply(&mut self, op: &ModelOp) -> Result<(), &'static str> {
+            match op.transaction_type {
+                TransactionType::Deposit | TransactionType::Withdrawal => {
+                    let withdrawal = op.transaction_type == TransactionType::Withdrawal;
+
+                    if self.locked {
+                        return Err("account_not_active");
+                    }
+
+                    if self.records.contains_key(&op.tx_id) {
+                        return Err("duplicate_transaction");
+                    }
+
+                    let amount = op.amount.ok_or("invalid_data")?;
+
+                    if amount < 0 {
+                        return Err("negative_amount");
+                    }
+
+                    if withdrawal {
+                        if amount > self.available {
+                            return Err("insufficient_balance");
+                        }
+
+                        self.available -= amount;
+                    } else {
+                        self.available += amount;
+                    }
+
+                    self.records.insert(
+                        op.tx_id,
+                        ModelRecord {
+                            withdrawal,
+                            amount,
+                            held: 0,
+                            disputed: false,
+                            charged_back: false,
+                        },
+                    );
+                }
+                TransactionType::Dispute => {
+                    let record = self
+                        .records
+                        .get_mut(&op.tx_id)
+                        .ok_or("transaction_not_found")?;
+
+                    if record.disputed {
+                        return Err("already_disputed");
+                    }
+
+                    if record.charged_back {
+                        return Err("invalid_dispute");
+                    }
+
+                    record.disputed = true;
+                    record.held = record.amount.min(self.available);
+                    self.available -= record.held;
+                    self.held += record.held;
+                }
+                TransactionType::Resolve => {
+                    let record = self
+                        .records
+                        .get_mut(&op.tx_id)
+                        .ok_or("transaction_not_found")?;
+
+                    if !record.disputed {
+                        return Err("resolve_not_disputed");
+                    }
+
+                    record.disputed = false;
+                    self.held -= record.held;
+                    self.available += record.held;
+
+                    // A resolved withdrawal is paid back to the client.
+                    if record.withdrawal {
+                        self.available += record.amount;
+                    }
+                }
+                TransactionType::Chargeback => {
+                    let record = self
+                        .records
+                        .get_mut(&op.tx_id)
+                        .ok_or("transaction_not_found")?;
+
+                    if !record.disputed {
+                        return Err("chargeback_not_disputed");
+                    }
+
+                    if record.withdrawal {
+                        return Err("invalid_chargeback");
+                    }
+
+                    record.disputed = false;
+                    record.charged_back = true;
+                    self.held -= record.held;
+                    self.owed += record.amount - record.held;
+                    self.locked = true;
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    fn model_op() -> impl Strategy<Value = ModelOp> {
+        let transaction_type = prop_oneof![
+            3 => Just(TransactionType::Deposit),
+            2 => Just(TransactionType::Withdrawal),
+            2 => Just(TransactionType::Dispute),
+            1 => Just(TransactionType::Resolve),
+            1 => Just(TransactionType::Chargeback),
+        ];
+        // Small amounts keep withdrawals and disputes within reach of the
+        // deposits, the others cover amounts up to 10^8 with four decimals.
+        let amount = prop_oneof![
+            4 => (0..1_000_000i64).prop_map(Some),
+            4 => (0..=1_000_000_000_000i64).prop_map(Some),
+            1 => (-1_000_000_000_000..0i64).prop_map(Some),
+            1 => Just(None),
+        ];
+
+        (transaction_type, 0..3u16, 0..6u32, amount).prop_map(
+            |(transaction_type, client_id, tx_id, amount)| ModelOp {
+                transaction_type,
+                client_id,
+                tx_id,
+                amount,
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn matches_reference_model(ops in proptest::collection::vec(model_op(), 1..60)) {
+            let mut processor = PaymentProcessor::new();
+            let mut model: BTreeMap<u16, ModelAccount> = BTreeMap::new();
+
+            for (index, op) in ops.iter().enumerate() {
+                let model_account = model.entry(op.client_id).or_default();
+                let expected = model_account.apply(op);
+                let actual = processor
+                    .try_process(op.transaction())
+                    .map_err(|e| e.code());
+                let account = &processor.get_accounts()[&op.client_id];
+
+                prop_assert_eq!(actual, expected, "decision of row {}", index + 1);
+                prop_assert_eq!(
+                    account.get_available_in_cent_parts() as i64,
+                    model_account.available,
+                    "available after row {}",
+                    index + 1
+                );
+                prop_assert_eq!(
+                    account.get_total_in_cent_parts() as i64,
+                    model_account.total(),
+                    "total after row {}",
+                    index + 1
+                );
+                prop_assert_eq!(
+                    account.get_held_in_cent_parts() as i64,
+                    model_account.held,
+                    "held after row {}",
+                    index + 1
+                );
+                prop_assert_eq!(
+                    account.get_owed_in_cent_parts() as i64,
+                    model_account.owed,
+                    "owed after row {}",
+                    index + 1
+                );
+                prop_assert_eq!(
+                    account.is_locked(),
+                    model_account.locked,
+                    "lock after row {}",
+                    index + 1
+                );
+            }
+
+            prop_assert!(processor.get_accounts().keys().eq(model.keys()));
+        }
+    }
+
+    #[test]
+    fn rejects_missing_input() {
+        assert!(Cli::try_parse_from(["rust_test"]).is_err());
+        assert!(Cli::try_parse_from(["rust_test", "validate"]).is_err());
+    }
+
+    #[test]
+    fn merges_inputs_by_timestamp() {
+        let read = |input: &'static str| RecordReader::from_reader(Box::new(input.as_bytes()));
+        let first =
+            read("type,client,tx,amount,timestamp\ndeposit,1,1,1.0,5\ndeposit,1,3,1.0,20\n");
+        let second =
+            read("type,client,tx,amount,timestamp\ndeposit,2,2,1.0,10\ndeposit,2,4,1.0,30\n");
+
+        let tx_ids: Vec<u32> = MergeByTimestamp::new(vec![first, second])
+            .map(|result| result.unwrap().transaction.tx_id)
+            .collect();
+
+        assert_eq!(tx_ids, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn reads_compressed_inputs() {
+        use std::io::Write;
+
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,0.5\n";
+        let directory = std::env::temp_dir();
+        let gzip_path = directory.join("rust_test_reads_compressed_inputs.csv.gz");
+        let zstd_path = directory.join("rust_test_reads_compressed_inputs.csv.zst");
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(input.as_bytes()).unwrap();
+        std::fs::write(&gzip_path, encoder.finish().unwrap()).unwrap();
+        std::fs::write(&zstd_path, zstd::encode_all(input.as_bytes(), 0).unwrap()).unwrap();
+
+        for path in [&gzip_path, &zstd_path].iter() {
+            let transactions: Vec<Transaction> = TransactionReader::from_path(path)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+
+            assert_eq!(transactions.len(), 2);
+            assert_eq!(
+                transactions[1].transaction_type,
+                TransactionType::Withdrawal
+            );
+
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn stops_at_truncated_compressed_input() {
+        use std::io::Write;
+
+        let input = "type,client,tx,amount\n".to_string() + &"deposit,1,1,1.0\n".repeat(1000);
+        let directory = std::env::temp_dir();
+        let gzip_path = directory.join("rust_test_stops_at_truncated_compressed_input.csv.gz");
+        let zstd_path = directory.join("rust_test_stops_at_truncated_compressed_input.csv.zst");
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(input.as_bytes()).unwrap();
+        let gzip = encoder.finish().unwrap();
+        let zstd = zstd::encode_all(input.as_bytes(), 0).unwrap();
+        std::fs::write(&gzip_path, &gzip[..gzip.len() / 2]).unwrap();
+        std::fs::write(&zstd_path, &zstd[..zstd.len() / 2]).unwrap();
+
+        for path in [&gzip_path, &zstd_path].iter() {
+            let results: Vec<_> = TransactionReader::from_path(path).unwrap().collect();
+
+            assert!(results.last().unwrap().as_ref().unwrap_err().fatal);
+            assert!(results[..results.len() - 1].iter().all(Result::is_ok));
+
+            let cli = Cli::try_parse_from(["rust_test", path.to_str().unwrap()]).unwrap();
+            let mut processor = PaymentProcessor::new();
+
+            let result = super::run(&cli.options, &mut processor, &mut std::io::sink(), |_| {
+                Ok(())
+            });
+
+            assert!(result.is_err());
+
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn parses_padded_and_reordered_columns() {
+        let input = "Amount , Client_ID, tx_id, Type
+ 1.5, 1, 1, DEPOSIT
+ , 1, 1, Dispute
+";
+        let transactions: Vec<Transaction> =
+            TransactionReader::from_reader(Box::new(input.as_bytes()))
+                .collect::<Result<_, _>>()
+                .unwrap();
+
+        assert_eq!(transactions[0].transaction_type, TransactionType::Deposit);
+        assert_eq!(transactions[0].client_id, 1);
+        assert_eq!(transactions[0].amount, Some(15000));
+        assert_eq!(transactions[1].transaction_type, TransactionType::Dispute);
+        assert_eq!(transactions[1].amount, None);
+
+        let input = "type,client,tx,amount
+deposit, 1, 1, 1.0
+dispute, 1, 1
+";
+        let transactions: Vec<Transaction> =
+            TransactionReader::from_reader(Box::new(input.as_bytes()))
+                .collect::<Result<_, _>>()
+                .unwrap();
+
+        assert_eq!(transactions[1].tx_id, 1);
+        assert_eq!(transactions[1].amount, None);
+    }
+
+    #[test]
+    fn reports_line_and_raw_record_of_invalid_rows() {
+        let input = "type,client,tx,amount
+deposit,1,1,1.0
+refund, 1, 2, 1.0
+deposit,1,3,1.0
+withdrawal,1,4,\"1,5\"
+";
+        let results: Vec<_> = TransactionReader::from_reader(Box::new(input.as_bytes())).collect();
+
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok());
+        assert!(results[2].is_ok());
+
+        let error = results[1].as_ref().unwrap_err();
+
+        assert_eq!(error.line, 3);
+        assert_eq!(error.raw, "refund, 1, 2, 1.0");
+        assert!(error.message.contains("unknown transaction type"));
+
+        let error = results[3].as_ref().unwrap_err();
+
+        assert_eq!(error.line, 5);
+        assert_eq!(error.raw, "withdrawal,1,4,\"1,5\"");
+    }
+
+    #[test]
+    fn reports_rows_and_headers_that_are_not_utf8() {
+        let input =
+            b"type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,\xff2,1.0\ndeposit,1,3,1.0\n";
+        let results: Vec<_> = TransactionReader::from_reader(Box::new(&input[..])).collect();
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(results[2].is_ok());
+
+        let error = results[1].as_ref().unwrap_err();
+
+        assert_eq!(error.line, 3);
+        assert_eq!(error.raw, "deposit,1,\u{fffd}2,1.0");
+        assert!(!error.fatal);
+
+        let input = b"type,cl\xffient,tx,amount\ndeposit,1,1,1.0\n";
+        let results: Vec<_> = TransactionReader::from_reader(Box::new(&input[..])).collect();
+
+        assert_eq!(results.len(), 1);
+
+        let error = results[0].as_ref().unwrap_err();
+
+        assert_eq!(error.line, 1);
+        assert_eq!(error.raw, "type,cl\u{fffd}ient,tx,amount");
+        assert!(error.fatal);
+    }
+
+    #[test]
+    fn describes_rejected_rows() {
+        let input = "type,client,tx,amount
+deposit,1,1,1.0
+refund,1,2,1.0
+withdrawal,1,3,5.0
+";
+        let mut processor = PaymentProcessor::new();
+        let mut rejects = Vec::new();
+
+        for result in RecordReader::from_reader(Box::new(input.as_bytes()))
+            .with_source("input.csv".to_string())
+        {
+            match result {
+                Ok(record) => {
+                    if let Err(e) = processor.try_process(record.transaction.clone()) {
+                        rejects.push(Reject::from_operation_error(&record, &e));
+                    }
+                }
+                Err(e) => rejects.push(Reject::from_parse_error(&e)),
+            }
+        }
+
+        assert_eq!(rejects.len(), 2);
+        assert_eq!(rejects[0].source, "input.csv");
+        assert_eq!(rejects[0].line, 3);
+        assert_eq!(rejects[0].record, "refund,1,2,1.0");
+        assert_eq!(rejects[0].code, "parse_error");
+        assert_eq!(rejects[1].line, 4);
+        assert_eq!(rejects[1].record, "withdrawal,1,3,5.0");
+        assert_eq!(rejects[1].code, "insufficient_balance");
+        assert_eq!(
+            rejects[1].message,
+            OperationError::InsufficientBalance(1, 3).to_string()
+        );
+
+        let mut output = Vec::new();
+        let mut writer = RecordWriter::new(&mut output, Format::Csv);
+        writer.write(&rejects[1]).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap().lines().next(),
+            Some("source,line,record,code,message")
+        );
+    }
+
+    #[test]
+    fn processes_every_input_format_identically() {
+        let csv = "type,client,tx,amount
+deposit,1,1,5.0
+deposit,2,2,3.0
+withdrawal,1,3,1.5
+dispute,2,2,
+";
+        let ndjson = r#"{"type":"deposit","client":1,"tx":1,"amount":5.0}
+{"type":"deposit","client":2,"tx":2,"amount":3.0}
+{"type":"withdrawal","client":1,"tx":3,"amount":1.5}
+{"type":"dispute","client":2,"tx":2}
+"#;
+        let json = format!("[{}]", ndjson.lines().collect::<Vec<_>>().join(","));
+
+        let process = |input: String, format| {
+            let mut processor = PaymentProcessor::new();
+
+            TransactionReader::with_format(Box::new(std::io::Cursor::new(input)), format)
+                .for_each(|transaction| processor.process(transaction.unwrap()));
+
+            let mut accounts: Vec<String> = processor
+                .get_accounts()
+                .values()
+                .map(|account| account.to_string())
+                .collect();
+            accounts.sort();
+            accounts
+        };
+
+        let expected = process(csv.to_string(), Format::Csv);
+
+        assert_eq!(expected.len(), 2);
+        assert_eq!(process(ndjson.to_string(), Format::Ndjson), expected);
+        assert_eq!(process(json, Format::Json), expected);
+    }
+
+    #[test]
+    fn writes_accounts_in_every_output_format() {
+        let mut processor = PaymentProcessor::new();
+
+        for (client_id, tx_id) in [(1, 1), (2, 2)].iter().copied() {
+            processor.process(Transaction {
+                client_id,
+                amount: Some(25000),
+                transaction_type: TransactionType::Deposit,
+                tx_id,
+                timestamp: None,
+            });
+        }
+
+        let mut accounts: Vec<_> = processor.get_accounts().values().collect();
+        accounts.sort_by_key(|account| account.to_string());
+
+        let write = |format| {
+            let mut output = Vec::new();
+            let mut writer = RecordWriter::new(&mut output, format);
+
+            accounts
+                .iter()
+                .for_each(|account| writer.write(account).unwrap());
+            writer.finish().unwrap();
+
+            String::from_utf8(output).unwrap()
+        };
+
+        let from_csv: Vec<serde_json::Value> =
+            csv::Reader::from_reader(write(Format::Csv).as_bytes())
+                .deserialize::<BTreeMap<String, String>>()
+                .map(|row| serde_json::to_value(row.unwrap()).unwrap())
+                .collect();
+        let from_json: Vec<serde_json::Value> = serde_json::from_str(&write(Format::Json)).unwrap();
+        let from_ndjson: Vec<serde_json::Value> = write(Format::Ndjson)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(from_json.len(), 2);
+        assert_eq!(from_json, from_ndjson);
+        assert_eq!(from_json[0]["client"], 1);
+        assert_eq!(from_json[0]["total"], "2.5000");
+        assert_eq!(from_csv[0]["client"], "1");
+        assert_eq!(from_csv[0]["total"], "2.5000");
+        assert_eq!(from_csv[1]["status"], from_json[1]["status"]);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn writes_accounts_and_ledger_to_parquet() {
+        use arrow_array::{Array, Decimal128Array, StringArray};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        use rust_test::columnar;
+
+        let mut processor = PaymentProcessor::new();
+        let mut ledger = Vec::new();
+        let transactions = vec![
+            (TransactionType::Deposit, 1, Some(123456789)),
+            (TransactionType::Withdrawal, 2, Some(200000000)),
+            (TransactionType::Deposit, 3, Some(-50000)),
+        ];
+
+        for (sequence, (transaction_type, tx_id, amount)) in transactions.into_iter().enumerate() {
+            let transaction = Transaction {
+                client_id: 1,
+                amount,
+                transaction_type,
+                tx_id,
+                timestamp: None,
+            };
+
+            ledger.push(ProcessedTransaction {
+                sequence: sequence as u64 + 1,
+                error: processor.try_process(transaction.clone()).err(),
+                transaction,
+            });
+        }
+
+        let directory = std::env::temp_dir();
+        let accounts_path = directory.join("rust_test_parquet_accounts.parquet");
+        let ledger_path = directory.join("rust_test_parquet_ledger.parquet");
+
+        let cli = Cli::try_parse_from([
+            "rust_test",
+            "transactions.csv",
+            "--only-locked",
+            "--parquet-accounts",
+            accounts_path.to_str().unwrap(),
+            "--parquet-ledger",
+            ledger_path.to_str().unwrap(),
+        ])
+        .unwrap();
+
+        super::write_parquet(&cli.options, &processor, &ledger).unwrap();
+
+        let read = |path: &std::path::Path| {
+            let file = std::fs::File::open(path).unwrap();
+            let batch = ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap()
+                .build()
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap();
+            std::fs::remove_file(path).unwrap();
+            batch
+        };
+
+        let accounts = read(&accounts_path);
+        let total = accounts
+            .column_by_name("total")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .unwrap();
+
+        assert_eq!(accounts.schema().as_ref(), &columnar::accounts_schema());
+        assert_eq!(accounts.num_rows(), 1);
+        assert_eq!(total.value_as_string(0), "12345.6789");
+
+        let ledger = read(&ledger_path);
+        let amount = ledger
+            .column_by_name("amount")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .unwrap();
+
+        let transaction_type = ledger
+            .column_by_name("type")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+
+        assert_eq!(ledger.schema().as_ref(), &columnar::ledger_schema());
+        assert_eq!(ledger.num_rows(), 3);
+        assert_eq!(transaction_type.value(1), "withdrawal");
+        assert_eq!(amount.value_as_string(0), "12345.6789");
+        assert_eq!(amount.value_as_string(1), "20000.0000");
+        assert!(ledger.column_by_name("error").unwrap().is_valid(1));
+        assert_eq!(amount.value_as_string(2), "-5.0000");
+    }
+
+    #[test]
+    fn writes_accounts_in_client_order_with_four_decimals() {
+        let input = "type,client,tx,amount
+deposit,3,3,1.5
+deposit,1,1,20
+deposit,2,2,0.0001
+deposit,5,5,12345.6789
+deposit,4,4,1677.7217
+";
+        let mut processor = PaymentProcessor::new();
+
+        for transaction in TransactionReader::from_reader(Box::new(input.as_bytes())) {
+            processor.process(transaction.unwrap());
+        }
+
+        let rows: Vec<String> = processor
+            .get_accounts()
+            .values()
+            .map(|account| account.to_string())
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                "1,20.0000,0.0000,20.0000,0.0000,false,active",
+                "2,0.0001,0.0000,0.0001,0.0000,false,active",
+                "3,1.5000,0.0000,1.5000,0.0000,false,active",
+                "4,1677.7217,0.0000,1677.7217,0.0000,false,active",
+                "5,12345.6789,0.0000,12345.6789,0.0000,false,active",
+            ]
+        );
+    }
+
+    #[test]
+    fn sorts_and_filters_written_accounts() {
+        let mut processor = PaymentProcessor::new();
+
+        for (client_id, amount) in [(1, 50000), (2, 0), (3, 100000), (4, 50000)].iter().copied() {
+            processor.process(Transaction {
+                client_id,
+                amount: Some(amount),
+                transaction_type: TransactionType::Deposit,
+                tx_id: client_id as u32,
+                timestamp: None,
+            });
+        }
+
+        let cli = Cli::try_parse_from([
+            "rust_test",
+            "in.csv",
+            "--sort",
+            "balance",
+            "--only-non-zero",
+        ])
+        .unwrap();
+        let clients: Vec<u16> = select_accounts(&cli.options, &processor)
+            .iter()
+            .map(|account| account.get_client_id())
+            .collect();
+
+        assert_eq!(clients, vec![3, 1, 4]);
+
+        let cli = Cli::try_parse_from(["rust_test", "in.csv", "--only-locked"]).unwrap();
+
+        assert!(select_accounts(&cli.options, &processor).is_empty());
+    }
 }
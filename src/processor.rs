@@ -1,40 +1,706 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-use crate::account::Account;
-use crate::transaction::Transaction;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
 
+use crate::account::{
+    format_amount, Account, AccountStatus, DisputeStatus, OperationError,
+};
+use crate::admin::{AdminTransaction, ReasonCode};
+use crate::config::{ExpiryAction, PolicyConfig};
+use crate::disputes::{self, OpenDispute};
+use crate::dry_run::DryRun;
+use crate::fraud::{Alert, FraudAction, FraudRule, RuleContext, RuleEngine};
+use crate::invariants::{self, AccountSnapshot, Violation};
+use crate::ledger::{Ledger, LedgerAccount, TrialBalance, TrialBalanceRow};
+use crate::limits::SECONDS_IN_DAY;
+use crate::statement::{AsOf, Balance, Statement, StatementEntry};
+use crate::stats::ProcessingStats;
+use crate::transaction::{Transaction, TransactionType};
+
+/// Dispute closed by the processor because it stayed open past its deadline.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiredDispute {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+
+    #[serde(rename = "tx")]
+    pub tx_id: u32,
+
+    pub action: ExpiryAction,
+    pub deadline: u64,
+}
+
+/// A transaction together with the outcome of processing it. `sequence` is
+/// the position of the transaction in the processed input.
+#[derive(Debug, Clone)]
+pub struct ProcessedTransaction {
+    pub sequence: u64,
+    pub transaction: Transaction,
+    pub error: Option<OperationError>,
+}
+
+impl ProcessedTransaction {
+    pub fn is_accepted(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl Serialize for ProcessedTransaction {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let transaction = &self.transaction;
+        let mut state = s.serialize_struct("ProcessedTransaction", 8)?;
+        state.serialize_field("sequence", &self.sequence)?;
+        state.serialize_field("type", &transaction.transaction_type)?;
+        state.serialize_field("client", &transaction.client_id)?;
+        state.serialize_field("tx", &transaction.tx_id)?;
+        state.serialize_field("amount", &transaction.amount.map(format_amount))?;
+        state.serialize_field("timestamp", &transaction.timestamp)?;
+        state.serialize_field(
+            "outcome",
+            if self.is_accepted() {
+                "accepted"
+            } else {
+                "rejected"
+            },
+        )?;
+        state.serialize_field("error", &self.error.as_ref().map(|e| e.to_string()))?;
+        state.end()
+    }
+}
+
+/// Transaction of a batch that was rejected, `index` is its position in the
+/// batch. Nothing of the batch has been applied.
+#[derive(Debug, Clone)]
+pub struct BatchError {
+    pub index: usize,
+    pub error: OperationError,
+}
+
+#[derive(Clone)]
 pub struct PaymentProcessor {
-    accounts: HashMap<u16, Account>,
+    accounts: BTreeMap<u16, Account>,
+    policy: PolicyConfig,
+    rules: RuleEngine,
+    alerts: Vec<Alert>,
+    dispute_deadlines: BTreeSet<(u64, u16, u32)>,
+    expired_disputes: Vec<ExpiredDispute>,
+    ledger: Ledger,
+    batch: Option<Batch>,
+    stats: ProcessingStats,
+    started: Option<Instant>,
+    check_invariants: bool,
+    violations: Vec<Violation>,
+}
+
+/// State needed to undo an open batch. Accounts are copied the first time
+/// the batch touches them, `None` marks accounts the batch created.
+#[derive(Clone)]
+struct Batch {
+    accounts: BTreeMap<u16, Option<Account>>,
+    dispute_deadlines: BTreeSet<(u64, u16, u32)>,
+    ledger: Ledger,
+    alerts: usize,
+    expired_disputes: usize,
+    violations: usize,
+    stats: ProcessingStats,
+}
+
+impl Default for PaymentProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PaymentProcessor {
     pub fn new() -> PaymentProcessor {
+        PaymentProcessor::with_policy(PolicyConfig::default())
+    }
+
+    pub fn with_policy(policy: PolicyConfig) -> PaymentProcessor {
         PaymentProcessor {
-            accounts: HashMap::new(),
+            accounts: BTreeMap::new(),
+            rules: RuleEngine::from_config(&policy.fraud),
+            policy,
+            alerts: Vec::new(),
+            dispute_deadlines: BTreeSet::new(),
+            expired_disputes: Vec::new(),
+            ledger: Ledger::new(),
+            batch: None,
+            stats: ProcessingStats::default(),
+            started: None,
+            check_invariants: false,
+            violations: Vec::new(),
         }
     }
 
+    pub fn add_rule<R: FraudRule + 'static>(&mut self, rule: R, action: FraudAction) {
+        self.rules.add_rule(rule, action);
+    }
+
+    /// Checks the account invariants after every operation, violations are
+    /// collected for `take_violations`. Off by default since it walks the
+    /// transactions of the account each time.
+    pub fn set_check_invariants(&mut self, enabled: bool) {
+        self.check_invariants = enabled;
+    }
+
     pub fn process(&mut self, transaction: Transaction) {
-        match self.accounts.get_mut(&transaction.client_id) {
-            Some(account) => match account.handle(transaction) {
-                Ok(_) => {}
-                Err(e) => eprintln!("Transaction error occured: {}", e),
-            },
-            None => {
-                let client_id = transaction.client_id;
-                let mut account = Account::new(client_id);
+        if let Err(e) = self.try_process(transaction) {
+            eprintln!("Transaction error occured: {}", e);
+        }
+    }
+
+    /// Applies all transactions or none of them. On the first rejected
+    /// transaction every change made by the batch is rolled back, including
+    /// alerts, expired disputes and invariant violations, and the processor
+    /// is left as it was.
+    pub fn process_batch<I>(&mut self, transactions: I) -> Result<usize, BatchError>
+    where
+        I: IntoIterator<Item = Transaction>,
+    {
+        self.batch = Some(Batch {
+            accounts: BTreeMap::new(),
+            dispute_deadlines: self.dispute_deadlines.clone(),
+            ledger: self.ledger.clone(),
+            alerts: self.alerts.len(),
+            expired_disputes: self.expired_disputes.len(),
+            violations: self.violations.len(),
+            stats: self.stats.clone(),
+        });
+
+        let mut applied = 0;
+
+        for (index, transaction) in transactions.into_iter().enumerate() {
+            if let Err(error) = self.try_process(transaction) {
+                self.rollback_batch();
+                return Err(BatchError { index, error });
+            }
+
+            applied += 1;
+        }
+
+        self.batch = None;
+
+        Ok(applied)
+    }
+
+    /// Runs the transactions against a copy of the processor and reports
+    /// what would happen, the processor itself is left untouched.
+    pub fn dry_run<I>(&self, transactions: I) -> DryRun
+    where
+        I: IntoIterator<Item = Transaction>,
+    {
+        let mut preview = self.clone();
+        let mut dry_run = DryRun::default();
+
+        for (index, transaction) in transactions.into_iter().enumerate() {
+            dry_run
+                .summary
+                .balance_changes
+                .entry(transaction.client_id)
+                .or_insert(0);
+
+            let processed = ProcessedTransaction {
+                sequence: index as u64 + 1,
+                error: preview.try_process(transaction.clone()).err(),
+                transaction,
+            };
+
+            dry_run.summary.record(&processed);
+            dry_run.outcomes.push(processed);
+        }
+
+        for (client_id, delta) in dry_run.summary.balance_changes.iter_mut() {
+            let before = self
+                .accounts
+                .get(client_id)
+                .map_or(0, Account::get_total_in_cent_parts);
+            let after = preview
+                .accounts
+                .get(client_id)
+                .map_or(0, Account::get_total_in_cent_parts);
+
+            *delta = after as i128 - before as i128;
+        }
+
+        dry_run
+    }
+
+    fn rollback_batch(&mut self) {
+        let batch = match self.batch.take() {
+            Some(batch) => batch,
+            None => return,
+        };
+
+        for (client_id, account) in batch.accounts {
+            match account {
+                Some(account) => self.accounts.insert(client_id, account),
+                None => self.accounts.remove(&client_id),
+            };
+        }
+
+        self.dispute_deadlines = batch.dispute_deadlines;
+        self.ledger = batch.ledger;
+        self.alerts.truncate(batch.alerts);
+        self.expired_disputes.truncate(batch.expired_disputes);
+        self.violations.truncate(batch.violations);
+        self.stats = batch.stats;
+    }
+
+    /// Copies the account before its first change within an open batch.
+    fn touch(&mut self, client_id: u16) {
+        if let Some(batch) = self.batch.as_mut() {
+            let accounts = &self.accounts;
+
+            batch
+                .accounts
+                .entry(client_id)
+                .or_insert_with(|| accounts.get(&client_id).cloned());
+        }
+    }
+
+    pub fn try_process(&mut self, transaction: Transaction) -> Result<(), OperationError> {
+        self.started.get_or_insert_with(Instant::now);
+
+        let transaction_type = transaction.transaction_type;
+        let (client_id, tx_id) = (transaction.client_id, transaction.tx_id);
+        let timestamp = transaction.timestamp;
+        let mut amount = transaction.amount_in_cent_parts();
+        let result = self.apply(transaction);
+
+        if result.is_ok() {
+            self.record_history(transaction_type, client_id, tx_id, timestamp);
+
+            // Only accepted rows move the expiry clock forward.
+            if let Some(timestamp) = timestamp {
+                self.expire_disputes(timestamp);
+            }
+        }
+
+        if let (TransactionType::Chargeback, Ok(())) = (transaction_type, &result) {
+            amount = self.charged_back_amount(client_id, tx_id);
+        }
+
+        self.stats
+            .record(transaction_type, result.as_ref().err(), amount);
+
+        result
+    }
+
+    /// Counts a row that could not be parsed into a transaction.
+    pub fn record_parse_error(&mut self) {
+        self.stats.rows_read += 1;
+    }
+
+    /// Statistics of everything processed so far, together with the current
+    /// state of the accounts.
+    pub fn stats(&self) -> ProcessingStats {
+        let mut stats = self.stats.clone();
+
+        stats.held = self
+            .accounts
+            .values()
+            .map(Account::get_held_in_cent_parts)
+            .fold(0, u64::saturating_add);
+        stats.accounts = self.accounts.len() as u64;
+        stats.locked_accounts = self
+            .accounts
+            .values()
+            .filter(|account| account.is_locked())
+            .count() as u64;
+        stats.elapsed_seconds = self
+            .started
+            .map_or(0.0, |started| started.elapsed().as_secs_f64());
+
+        if stats.elapsed_seconds > 0.0 {
+            stats.rows_per_second = stats.rows_read as f64 / stats.elapsed_seconds;
+        }
+
+        stats
+    }
+
+    /// Movements of a client with a timestamp within `from..=to`, all of them
+    /// when no range is given. `None` when the client has not been seen.
+    pub fn statement(
+        &self,
+        client_id: u16,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> Option<Statement> {
+        self.accounts
+            .get(&client_id)
+            .map(|account| Statement::new(client_id, account.get_history(), from, to))
+    }
+
+    /// General ledger with the postings of every accepted operation.
+    pub fn get_ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// Ledger balances checked against the client accounts, every client
+    /// gets an available and a held row even when nothing was posted to it.
+    /// A receivable row is checked against what the client owes.
+    pub fn trial_balance(&self) -> TrialBalance {
+        let mut trial_balance = self.ledger.trial_balance();
+        let rows = &mut trial_balance.rows;
+
+        for account in self.accounts.values() {
+            let client_id = account.get_client_id();
+            let mut balances = vec![
+                (
+                    LedgerAccount::ClientAvailable(client_id),
+                    account.get_available_in_cent_parts() as i128,
+                ),
+                (
+                    LedgerAccount::ClientHeld(client_id),
+                    account.get_held_in_cent_parts() as i128,
+                ),
+            ];
+
+            if account.get_owed_in_cent_parts() > 0
+                || rows
+                    .binary_search_by_key(&LedgerAccount::ClientReceivable(client_id), |row| row.account)
+                    .is_ok()
+            {
+                balances.push((
+                    LedgerAccount::ClientReceivable(client_id),
+                    -(account.get_owed_in_cent_parts() as i128),
+                ));
+            }
 
-                match account.handle(transaction) {
-                    Ok(_) => {}
-                    Err(e) => eprintln!("Transaction error occured: {}", e),
+            for (ledger_account, expected) in balances {
+                let expected = Some(expected);
+
+                match rows.binary_search_by_key(&ledger_account, |row| row.account) {
+                    Ok(index) => rows[index].expected = expected,
+                    Err(index) => rows.insert(
+                        index,
+                        TrialBalanceRow {
+                            account: ledger_account,
+                            balance: 0,
+                            expected,
+                        },
+                    ),
+                }
+            }
+        }
+
+        trial_balance
+    }
+
+    /// Transactions currently under dispute, aged relative to `now`.
+    pub fn open_disputes(&self, now: u64) -> Vec<OpenDispute> {
+        disputes::open_disputes(self.accounts.values(), now)
+    }
+
+    /// Latest transaction timestamp seen over all accounts.
+    pub fn latest_timestamp(&self) -> Option<u64> {
+        self.accounts
+            .values()
+            .filter_map(Account::get_last_timestamp)
+            .max()
+    }
+
+    /// Balances of a client as they were at a point of the processed input,
+    /// answered from the client history without replaying. `None` when the
+    /// client has not been seen or the transaction is not in its history.
+    pub fn balance_as_of(&self, client_id: u16, as_of: AsOf) -> Option<Balance> {
+        let account = self.accounts.get(&client_id)?;
+
+        Balance::as_of(client_id, account.get_history(), as_of)
+    }
+
+    /// Adds the balances after an accepted transaction to the client history.
+    /// Its sequence is the row being processed, expired disputes share the
+    /// sequence of the row that expired them.
+    fn record_history(
+        &mut self,
+        transaction_type: TransactionType,
+        client_id: u16,
+        tx_id: u32,
+        timestamp: Option<u64>,
+    ) {
+        let sequence = self.stats.rows_read + 1;
+        let account = match self.accounts.get_mut(&client_id) {
+            Some(account) => account,
+            None => return,
+        };
+        let (amount, dispute_status) = match account.get_transaction(tx_id) {
+            Some(record) => (
+                record.transaction.amount_in_cent_parts(),
+                record.dispute_status,
+            ),
+            None => (0, DisputeStatus::Undisputed),
+        };
+
+        account.push_history(StatementEntry {
+            sequence,
+            timestamp,
+            transaction_type,
+            tx_id,
+            amount,
+            available: account.get_available_in_cent_parts(),
+            held: account.get_held_in_cent_parts(),
+            total: account.get_total_in_cent_parts(),
+            dispute_status,
+        });
+    }
+
+    fn charged_back_amount(&self, client_id: u16, tx_id: u32) -> u64 {
+        self.accounts
+            .get(&client_id)
+            .and_then(|account| account.get_transaction(tx_id))
+            .map_or(0, |record| record.transaction.amount_in_cent_parts())
+    }
+
+    fn apply(&mut self, transaction: Transaction) -> Result<(), OperationError> {
+        let now = transaction.timestamp.unwrap_or_else(current_timestamp);
+
+        let retention = self
+            .policy
+            .limits
+            .longest_window()
+            .max(self.rules.longest_window())
+            .max(SECONDS_IN_DAY);
+        let client_id = transaction.client_id;
+
+        self.touch(client_id);
+
+        let account = self
+            .accounts
+            .entry(client_id)
+            .or_insert_with(|| Account::new(client_id));
+
+        if let (Some(timestamp), Some(last)) = (transaction.timestamp, account.get_last_timestamp())
+        {
+            if timestamp.saturating_add(self.policy.timestamps.reorder_window_seconds) < last {
+                return Err(OperationError::TimestampOutOfOrder(
+                    transaction.client_id,
+                    transaction.tx_id,
+                ));
+            }
+        }
+
+        account.get_activity_mut().prune(now, retention);
+        self.policy.limits.check(account, &transaction, now)?;
+
+        let transaction_type = transaction.transaction_type;
+        let amount = transaction.amount_in_cent_parts();
+
+        let before = self.check_invariants.then(|| AccountSnapshot::of(account));
+        let result = account.handle_with_policy(transaction.clone(), &self.policy.disputes);
+
+        if let Some(before) = before {
+            self.violations.extend(invariants::check(
+                &before,
+                account,
+                &transaction,
+                result.as_ref().ok(),
+            ));
+        }
+
+        self.ledger.post(result?);
+
+        if let Some(timestamp) = transaction.timestamp {
+            account.set_last_timestamp(timestamp);
+
+            if let (TransactionType::Dispute, Some(expiry)) =
+                (transaction_type, self.policy.disputes.expiry_seconds)
+            {
+                self.dispute_deadlines.insert((
+                    timestamp.saturating_add(expiry),
+                    client_id,
+                    transaction.tx_id,
+                ));
+            }
+        }
+
+        account
+            .get_activity_mut()
+            .record(now, transaction_type, amount);
+
+        let alerts = self.rules.evaluate(&RuleContext {
+            transaction: &transaction,
+            account,
+            timestamp: now,
+        });
+
+        for alert in alerts {
+            match alert.action {
+                FraudAction::Log => {}
+                FraudAction::HoldFunds => {
+                    if let Ok(Some(entry)) = account.hold_funds(&transaction) {
+                        self.ledger.post(entry);
+                    }
+                }
+                FraudAction::FreezeAccount => {
+                    if account.get_status() == AccountStatus::Active {
+                        account.set_status(AccountStatus::Frozen, ReasonCode::FraudInvestigation);
+                    }
+                }
+                FraudAction::LockAccount => {
+                    account.set_status(AccountStatus::Locked, ReasonCode::FraudInvestigation);
+                }
+            }
+
+            self.alerts.push(alert);
+        }
+
+        Ok(())
+    }
+
+    /// Closes every dispute whose deadline is not after `now`, according to the
+    /// dispute policy. Called after each accepted timestamped transaction, so it
+    /// only has to be called directly to flush disputes at the end of the input.
+    pub fn expire_disputes(&mut self, now: u64) {
+        let expiry = match self.policy.disputes.expiry_seconds {
+            Some(expiry) => expiry,
+            None => return,
+        };
+
+        while let Some(&(deadline, client_id, tx_id)) = self.dispute_deadlines.iter().next() {
+            if deadline > now {
+                break;
+            }
+
+            self.dispute_deadlines.remove(&(deadline, client_id, tx_id));
+            self.touch(client_id);
+
+            let account = match self.accounts.get_mut(&client_id) {
+                Some(account) => account,
+                None => continue,
+            };
+
+            let original_type = match account.get_transaction(tx_id) {
+                Some(record)
+                    if record.dispute_status == DisputeStatus::Disputed
+                        && record.disputed_at.map(|at| at.saturating_add(expiry))
+                            == Some(deadline) =>
+                {
+                    record.transaction.transaction_type
                 }
+                _ => continue,
+            };
+
+            let action = match (self.policy.disputes.on_expiry, original_type) {
+                (ExpiryAction::Chargeback, TransactionType::Deposit) => ExpiryAction::Chargeback,
+                _ => ExpiryAction::Resolve,
+            };
+
+            let transaction_type = match action {
+                ExpiryAction::Resolve => TransactionType::Resolve,
+                ExpiryAction::Chargeback => TransactionType::Chargeback,
+            };
 
-                self.accounts.insert(client_id, account);
+            let transaction = Transaction {
+                transaction_type,
+                client_id,
+                tx_id,
+                amount: None,
+                timestamp: Some(deadline),
+            };
+
+            let before = self.check_invariants.then(|| AccountSnapshot::of(account));
+            let result = account.handle(transaction.clone());
+
+            if let Some(before) = before {
+                self.violations.extend(invariants::check(
+                    &before,
+                    account,
+                    &transaction,
+                    result.as_ref().ok(),
+                ));
             }
+
+            if let Ok(entry) = result {
+                self.ledger.post(entry);
+
+                account
+                    .get_activity_mut()
+                    .record(deadline, transaction_type, 0);
+
+                self.record_history(transaction_type, client_id, tx_id, Some(deadline));
+
+                if action == ExpiryAction::Chargeback {
+                    let amount = self.charged_back_amount(client_id, tx_id);
+
+                    self.stats.record_movement(transaction_type, amount);
+                }
+
+                self.expired_disputes.push(ExpiredDispute {
+                    client_id,
+                    tx_id,
+                    action,
+                    deadline,
+                });
+            }
+        }
+    }
+
+    /// Applies an account lifecycle operation. Admin operations never create
+    /// accounts, so the client must have been seen in regular input first.
+    pub fn process_admin(
+        &mut self,
+        transaction: AdminTransaction,
+    ) -> Result<&Account, OperationError> {
+        let account = match self.accounts.get_mut(&transaction.client_id) {
+            Some(account) => account,
+            None => return Err(OperationError::ClientNotFound(transaction.client_id)),
         };
+
+        for entry in account.handle_admin(transaction)? {
+            self.ledger.post(entry);
+        }
+
+        Ok(account)
     }
 
-    pub fn get_accounts(&self) -> &HashMap<u16, Account> {
+    /// Accounts ordered by client id.
+    pub fn get_accounts(&self) -> &BTreeMap<u16, Account> {
         &self.accounts
     }
+
+    /// Returns alerts raised since the last call.
+    pub fn take_alerts(&mut self) -> Vec<Alert> {
+        std::mem::take(&mut self.alerts)
+    }
+
+    /// Returns disputes closed automatically since the last call.
+    pub fn take_expired_disputes(&mut self) -> Vec<ExpiredDispute> {
+        std::mem::take(&mut self.expired_disputes)
+    }
+
+    /// Returns invariant violations found since the last call.
+    pub fn take_violations(&mut self) -> Vec<Violation> {
+        std::mem::take(&mut self.violations)
+    }
+}
+
+/// Seconds since unix epoch by the wall clock.
+pub fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+impl fmt::Display for ExpiredDispute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self.action {
+            ExpiryAction::Resolve => "resolved",
+            ExpiryAction::Chargeback => "charged back",
+        };
+
+        write!(
+            f,
+            "Client {} Dispute of transaction {} {} after deadline {}",
+            self.client_id, self.tx_id, action, self.deadline
+        )
+    }
 }
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Read;
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::account::{format_cent_delta, format_cent_parts, parse_cent_parts, Account};
+
+/// Closing balances of a client as reported by a partner. Amounts are read
+/// as exact decimals and held in cent parts.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExpectedBalance {
+    pub client: u16,
+
+    #[serde(deserialize_with = "deserialize_cent_parts")]
+    pub available: u64,
+
+    #[serde(deserialize_with = "deserialize_cent_parts")]
+    pub held: u64,
+
+    #[serde(deserialize_with = "deserialize_cent_parts")]
+    pub total: u64,
+
+    /// Chargeback shortfalls the client still owes, zero when the column is
+    /// absent.
+    #[serde(default, deserialize_with = "deserialize_cent_parts")]
+    pub owed: u64,
+
+    pub locked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BreakKind {
+    /// Expected but not among the processed accounts.
+    Missing,
+    /// Processed but not expected.
+    Extra,
+    /// Present on both sides with a different value.
+    Mismatch,
+}
+
+/// Difference between the expected and the processed balances. `delta` is
+/// processed minus expected, for amount fields only.
+#[derive(Debug, Clone, Serialize)]
+pub struct Break {
+    pub client: u16,
+    pub kind: BreakKind,
+    pub field: Option<&'static str>,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+    pub delta: Option<String>,
+}
+
+impl ExpectedBalance {
+    /// Reads `client,available,held,total,owed,locked` csv rows, `owed` may be
+    /// left out. A client may appear only once.
+    pub fn read_csv<R: Read>(reader: R) -> Result<Vec<ExpectedBalance>, Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut clients = BTreeMap::new();
+
+        for result in reader.deserialize() {
+            let expected: ExpectedBalance = result?;
+            let client = expected.client;
+
+            if clients.insert(client, expected).is_some() {
+                return Err(format!("client {} appears more than once", client).into());
+            }
+        }
+
+        Ok(clients.into_values().collect())
+    }
+}
+
+/// Compares expected balances with the processed accounts, breaks are
+/// ordered by client.
+pub fn reconcile<'a, I>(expected: &[ExpectedBalance], accounts: I) -> Vec<Break>
+where
+    I: IntoIterator<Item = &'a Account>,
+{
+    let mut accounts: BTreeMap<u16, &Account> = accounts
+        .into_iter()
+        .map(|account| (account.get_client_id(), account))
+        .collect();
+    let mut breaks = Vec::new();
+
+    for expected in expected {
+        let account = match accounts.remove(&expected.client) {
+            Some(account) => account,
+            None => {
+                breaks.push(Break::new(expected.client, BreakKind::Missing));
+                continue;
+            }
+        };
+
+        let amounts = [
+            (
+                "available",
+                expected.available,
+                account.get_available_in_cent_parts(),
+            ),
+            ("held", expected.held, account.get_held_in_cent_parts()),
+            ("total", expected.total, account.get_total_in_cent_parts()),
+            ("owed", expected.owed, account.get_owed_in_cent_parts()),
+        ];
+
+        for (field, expected_amount, actual_amount) in amounts.iter() {
+            if expected_amount != actual_amount {
+                breaks.push(Break {
+                    field: Some(field),
+                    expected: Some(format_cent_parts(*expected_amount)),
+                    actual: Some(format_cent_parts(*actual_amount)),
+                    delta: Some(format_cent_delta(
+                        *actual_amount as i128 - *expected_amount as i128,
+                    )),
+                    ..Break::new(expected.client, BreakKind::Mismatch)
+                });
+            }
+        }
+
+        if expected.locked != account.is_locked() {
+            breaks.push(Break {
+                field: Some("locked"),
+                expected: Some(expected.locked.to_string()),
+                actual: Some(account.is_locked().to_string()),
+                ..Break::new(expected.client, BreakKind::Mismatch)
+            });
+        }
+    }
+
+    breaks.extend(
+        accounts
+            .into_keys()
+            .map(|client| Break::new(client, BreakKind::Extra)),
+    );
+    breaks.sort_by_key(|reconcile_break| reconcile_break.client);
+
+    breaks
+}
+
+impl Break {
+    fn new(client: u16, kind: BreakKind) -> Break {
+        Break {
+            client,
+            kind,
+            field: None,
+            expected: None,
+            actual: None,
+            delta: None,
+        }
+    }
+}
+
+fn deserialize_cent_parts<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    parse_cent_parts(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+}
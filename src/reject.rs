@@ -0,0 +1,40 @@
+use serde::Serialize;
+
+use crate::account::OperationError;
+use crate::input::{InputRecord, ParseError};
+
+/// Error code of rows that could not be parsed.
+pub const PARSE_ERROR: &str = "parse_error";
+
+/// Input row that was not applied, either because it could not be parsed or
+/// because the processor rejected it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Reject {
+    pub source: String,
+    pub line: u64,
+    pub record: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl Reject {
+    pub fn from_parse_error(error: &ParseError) -> Reject {
+        Reject {
+            source: error.source.clone(),
+            line: error.line,
+            record: error.raw.clone(),
+            code: PARSE_ERROR.to_string(),
+            message: error.message.clone(),
+        }
+    }
+
+    pub fn from_operation_error(record: &InputRecord, error: &OperationError) -> Reject {
+        Reject {
+            source: record.source.clone(),
+            line: record.line,
+            record: record.raw.clone(),
+            code: error.code().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
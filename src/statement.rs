@@ -0,0 +1,256 @@
+use std::fmt;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+use crate::account::{format_cent_parts, DisputeStatus};
+use crate::transaction::TransactionType;
+
+/// Accepted transaction of a client with the balances it resulted in.
+/// `sequence` is the position of the transaction in the processed input,
+/// `amount` is the amount of the transaction or, for disputes, resolves and
+/// chargebacks, of the transaction they refer to. Amounts are in cent parts.
+#[derive(Debug, Clone)]
+pub struct StatementEntry {
+    pub sequence: u64,
+    pub timestamp: Option<u64>,
+    pub transaction_type: TransactionType,
+    pub tx_id: u32,
+    pub amount: u64,
+    pub available: u64,
+    pub held: u64,
+    pub total: u64,
+    pub dispute_status: DisputeStatus,
+}
+
+/// Point in the processed input a balance is asked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AsOf {
+    /// After the row with this sequence number.
+    Sequence(u64),
+    /// Counting the movements stamped at or before this time.
+    Timestamp(u64),
+    /// After the deposit or withdrawal with this transaction id.
+    Transaction(u32),
+}
+
+/// Balances of a client at a point in time. `sequence` and `timestamp`
+/// belong to the last movement included, both are `None` when there was
+/// none yet.
+#[derive(Debug, Clone)]
+pub struct Balance {
+    pub client_id: u16,
+    pub sequence: Option<u64>,
+    pub timestamp: Option<u64>,
+    pub available: u64,
+    pub held: u64,
+    pub total: u64,
+}
+
+impl Balance {
+    /// Looks the point up in the history of a client. Returns `None` when
+    /// asked for a transaction that is not part of the history.
+    pub fn as_of(client_id: u16, history: &[StatementEntry], as_of: AsOf) -> Option<Balance> {
+        let last = match as_of {
+            AsOf::Sequence(sequence) => history
+                .iter()
+                .take_while(|entry| entry.sequence <= sequence)
+                .last(),
+            AsOf::Timestamp(timestamp) => {
+                return Some(Balance::at_time(client_id, history, timestamp))
+            }
+            AsOf::Transaction(tx_id) => Some(history.iter().find(|entry| {
+                entry.tx_id == tx_id
+                    && matches!(
+                        entry.transaction_type,
+                        TransactionType::Deposit | TransactionType::Withdrawal
+                    )
+            })?),
+        };
+
+        let balance = match last {
+            Some(entry) => Balance {
+                client_id,
+                sequence: Some(entry.sequence),
+                timestamp: entry.timestamp,
+                available: entry.available,
+                held: entry.held,
+                total: entry.total,
+            },
+            None => Balance {
+                client_id,
+                sequence: None,
+                timestamp: None,
+                available: 0,
+                held: 0,
+                total: 0,
+            },
+        };
+
+        Some(balance)
+    }
+
+    /// Sums the movements stamped at or before `timestamp`, in whatever order
+    /// they were processed, so movements processed earlier but stamped later
+    /// are left out. A movement without a timestamp counts from the next
+    /// stamped movement processed after it. A withdrawal stamped before the
+    /// deposit that funded it can take a sum below zero, which is reported
+    /// as zero.
+    fn at_time(client_id: u16, history: &[StatementEntry], timestamp: u64) -> Balance {
+        let mut previous = [0i128; 3];
+        let mut pending = [0i128; 3];
+        let mut sums = [0i128; 3];
+        let mut last: Option<&StatementEntry> = None;
+
+        for entry in history {
+            let balances = [entry.available, entry.held, entry.total].map(i128::from);
+
+            for (pending, (balance, previous)) in
+                pending.iter_mut().zip(balances.iter().zip(&previous))
+            {
+                *pending += balance - previous;
+            }
+
+            previous = balances;
+
+            let at = match entry.timestamp {
+                Some(at) => at,
+                None => continue,
+            };
+
+            if at <= timestamp {
+                for (sum, pending) in sums.iter_mut().zip(&pending) {
+                    *sum += pending;
+                }
+
+                if last.is_none_or(|last| last.timestamp <= Some(at)) {
+                    last = Some(entry);
+                }
+            }
+
+            pending = [0; 3];
+        }
+
+        let [available, held, total] = sums.map(|sum| sum.max(0) as u64);
+
+        Balance {
+            client_id,
+            sequence: last.map(|entry| entry.sequence),
+            timestamp: last.and_then(|entry| entry.timestamp),
+            available,
+            held,
+            total,
+        }
+    }
+}
+
+/// Movements of a single client, optionally limited to a time range.
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub client_id: u16,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub entries: Vec<StatementEntry>,
+}
+
+impl Statement {
+    /// Keeps the entries with a timestamp within `from..=to`. Entries
+    /// without a timestamp are only kept when no range is given.
+    pub fn new<'a, I>(client_id: u16, entries: I, from: Option<u64>, to: Option<u64>) -> Statement
+    where
+        I: IntoIterator<Item = &'a StatementEntry>,
+    {
+        let in_range = |entry: &&StatementEntry| match entry.timestamp {
+            Some(timestamp) => {
+                from.is_none_or(|from| timestamp >= from) && to.is_none_or(|to| timestamp <= to)
+            }
+            None => from.is_none() && to.is_none(),
+        };
+
+        Statement {
+            client_id,
+            from,
+            to,
+            entries: entries.into_iter().filter(in_range).cloned().collect(),
+        }
+    }
+}
+
+impl Serialize for StatementEntry {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = s.serialize_struct("StatementEntry", 9)?;
+        state.serialize_field("sequence", &self.sequence)?;
+        state.serialize_field("timestamp", &self.timestamp)?;
+        state.serialize_field("type", &self.transaction_type)?;
+        state.serialize_field("tx", &self.tx_id)?;
+        state.serialize_field("amount", &format_cent_parts(self.amount))?;
+        state.serialize_field("available", &format_cent_parts(self.available))?;
+        state.serialize_field("held", &format_cent_parts(self.held))?;
+        state.serialize_field("total", &format_cent_parts(self.total))?;
+        state.serialize_field("dispute_status", &self.dispute_status)?;
+        state.end()
+    }
+}
+
+impl Serialize for Balance {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = s.serialize_struct("Balance", 6)?;
+        state.serialize_field("client", &self.client_id)?;
+        state.serialize_field("sequence", &self.sequence)?;
+        state.serialize_field("timestamp", &self.timestamp)?;
+        state.serialize_field("available", &format_cent_parts(self.available))?;
+        state.serialize_field("held", &format_cent_parts(self.held))?;
+        state.serialize_field("total", &format_cent_parts(self.total))?;
+        state.end()
+    }
+}
+
+impl fmt::Display for AsOf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AsOf::Sequence(sequence) => write!(f, "row {}", sequence),
+            AsOf::Timestamp(timestamp) => write!(f, "time {}", timestamp),
+            AsOf::Transaction(tx_id) => write!(f, "transaction {}", tx_id),
+        }
+    }
+}
+
+/// Printable layout with one aligned row per entry.
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bound = |bound: Option<u64>| bound.map_or("-".to_string(), |bound| bound.to_string());
+
+        writeln!(f, "Statement for client {}", self.client_id)?;
+        writeln!(f, "Period: {} to {}", bound(self.from), bound(self.to))?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>8} {:>12} {:<10} {:>10} {:>14} {:>14} {:>14} {:>14}  dispute",
+            "sequence", "timestamp", "type", "tx", "amount", "available", "held", "total"
+        )?;
+
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "{:>8} {:>12} {:<10} {:>10} {:>14} {:>14} {:>14} {:>14}  {}",
+                entry.sequence,
+                bound(entry.timestamp),
+                entry.transaction_type.to_string(),
+                entry.tx_id,
+                format_cent_parts(entry.amount),
+                format_cent_parts(entry.available),
+                format_cent_parts(entry.held),
+                format_cent_parts(entry.total),
+                entry.dispute_status
+            )?;
+        }
+
+        write!(f, "{} entries", self.entries.len())
+    }
+}
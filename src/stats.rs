@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use crate::account::{format_cent_parts, OperationError};
+use crate::transaction::TransactionType;
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct OutcomeCounts {
+    pub accepted: u64,
+    pub rejected: u64,
+}
+
+/// Totals of a processing run. Amounts are in cent parts, `held`,
+/// `accounts` and `locked_accounts` describe the accounts when the
+/// statistics were taken.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProcessingStats {
+    pub rows_read: u64,
+    pub rows_parsed: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub by_type: BTreeMap<String, OutcomeCounts>,
+    pub rejects: BTreeMap<String, u64>,
+
+    #[serde(serialize_with = "serialize_cent_parts")]
+    pub deposited: u64,
+
+    #[serde(serialize_with = "serialize_cent_parts")]
+    pub withdrawn: u64,
+
+    #[serde(serialize_with = "serialize_cent_parts")]
+    pub held: u64,
+
+    #[serde(serialize_with = "serialize_cent_parts")]
+    pub charged_back: u64,
+
+    pub accounts: u64,
+    pub locked_accounts: u64,
+    pub elapsed_seconds: f64,
+    pub rows_per_second: f64,
+}
+
+impl ProcessingStats {
+    /// Counts a transaction, `amount` is the amount it moved in cent parts.
+    pub(crate) fn record(
+        &mut self,
+        transaction_type: TransactionType,
+        error: Option<&OperationError>,
+        amount: u64,
+    ) {
+        let counts = self
+            .by_type
+            .entry(transaction_type.to_string())
+            .or_default();
+
+        self.rows_read += 1;
+        self.rows_parsed += 1;
+
+        if let Some(e) = error {
+            self.rejected += 1;
+            counts.rejected += 1;
+            *self.rejects.entry(e.code().to_string()).or_insert(0) += 1;
+            return;
+        }
+
+        self.accepted += 1;
+        counts.accepted += 1;
+        self.record_movement(transaction_type, amount);
+    }
+
+    pub(crate) fn record_movement(&mut self, transaction_type: TransactionType, amount: u64) {
+        match transaction_type {
+            TransactionType::Deposit => self.deposited = self.deposited.saturating_add(amount),
+            TransactionType::Withdrawal => self.withdrawn = self.withdrawn.saturating_add(amount),
+            TransactionType::Chargeback => {
+                self.charged_back = self.charged_back.saturating_add(amount)
+            }
+            TransactionType::Dispute | TransactionType::Resolve => {}
+        }
+    }
+}
+
+fn serialize_cent_parts<S>(amount: &u64, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.serialize_str(&format_cent_parts(*amount))
+}
+
+impl fmt::Display for ProcessingStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "rows read: {}", self.rows_read)?;
+        writeln!(f, "rows parsed: {}", self.rows_parsed)?;
+        writeln!(f, "accepted: {}", self.accepted)?;
+        writeln!(f, "rejected: {}", self.rejected)?;
+
+        for (transaction_type, counts) in &self.by_type {
+            writeln!(
+                f,
+                "{}: {} accepted, {} rejected",
+                transaction_type, counts.accepted, counts.rejected
+            )?;
+        }
+
+        for (code, count) in &self.rejects {
+            writeln!(f, "rejected {}: {}", code, count)?;
+        }
+
+        writeln!(f, "deposited: {}", format_cent_parts(self.deposited))?;
+        writeln!(f, "withdrawn: {}", format_cent_parts(self.withdrawn))?;
+        writeln!(f, "held: {}", format_cent_parts(self.held))?;
+        writeln!(f, "charged back: {}", format_cent_parts(self.charged_back))?;
+        writeln!(f, "accounts: {}", self.accounts)?;
+        writeln!(f, "locked accounts: {}", self.locked_accounts)?;
+        writeln!(f, "elapsed seconds: {:.3}", self.elapsed_seconds)?;
+        write!(f, "rows per second: {:.0}", self.rows_per_second)
+    }
+}
@@ -1,6 +1,12 @@
-use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, Deserialize)]
+use chrono::DateTime;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::account::{format_amount, parse_amount};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -10,17 +16,128 @@ pub enum TransactionType {
     Chargeback,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub struct Transaction {
-    #[serde(rename(deserialize = "type"))]
+    #[serde(rename = "type", alias = "transaction_type")]
     pub transaction_type: TransactionType,
 
-    #[serde(rename(deserialize = "client"))]
+    #[serde(rename = "client", alias = "client_id")]
     pub client_id: u16,
 
-    #[serde(rename(deserialize = "tx"))]
+    #[serde(rename = "tx", alias = "tx_id", alias = "transaction_id")]
     pub tx_id: u32,
 
-    pub amount: Option<f32>,
+    /// Cent parts, read exactly from the decimal text. Negative amounts are
+    /// kept so that processing can reject them as such.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_amount",
+        serialize_with = "serialize_amount"
+    )]
+    pub amount: Option<i64>,
+
+    /// Seconds since unix epoch, given either as a number or RFC 3339 text.
+    #[serde(default, alias = "time", deserialize_with = "deserialize_timestamp")]
+    pub timestamp: Option<u64>,
+}
+
+impl Transaction {
+    /// Amount in cent parts, zero when it is missing or negative.
+    pub fn amount_in_cent_parts(&self) -> u64 {
+        self.amount.map_or(0, |amount| amount.max(0) as u64)
+    }
+}
+
+impl FromStr for TransactionType {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<TransactionType, String> {
+        match text.trim().to_lowercase().as_str() {
+            "deposit" => Ok(TransactionType::Deposit),
+            "withdrawal" => Ok(TransactionType::Withdrawal),
+            "dispute" => Ok(TransactionType::Dispute),
+            "resolve" => Ok(TransactionType::Resolve),
+            "chargeback" => Ok(TransactionType::Chargeback),
+            _ => Err(format!("unknown transaction type {}", text)),
+        }
+    }
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransactionType::Deposit => write!(f, "deposit"),
+            TransactionType::Withdrawal => write!(f, "withdrawal"),
+            TransactionType::Dispute => write!(f, "dispute"),
+            TransactionType::Resolve => write!(f, "resolve"),
+            TransactionType::Chargeback => write!(f, "chargeback"),
+        }
+    }
+}
+
+/// Transaction types are matched case insensitively.
+impl<'de> Deserialize<'de> for TransactionType {
+    fn deserialize<D>(deserializer: D) -> Result<TransactionType, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+fn deserialize_amount<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(text) => parse_amount(&text).map(Some).map_err(de::Error::custom),
+    }
+}
+
+fn serialize_amount<S>(amount: &Option<i64>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    amount.map(format_amount).serialize(s)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTimestamp {
+    Epoch(u64),
+    Text(String),
+}
+
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<RawTimestamp>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(RawTimestamp::Epoch(seconds)) => Ok(Some(seconds)),
+        Some(RawTimestamp::Text(text)) => parse_timestamp(&text).map_err(de::Error::custom),
+    }
+}
+
+/// Parses epoch seconds or an RFC 3339 date time, blank text means no timestamp.
+pub fn parse_timestamp(text: &str) -> Result<Option<u64>, String> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    if let Ok(seconds) = text.parse::<u64>() {
+        return Ok(Some(seconds));
+    }
+
+    match DateTime::parse_from_rfc3339(text) {
+        Ok(date_time) if date_time.timestamp() >= 0 => Ok(Some(date_time.timestamp() as u64)),
+        Ok(_) => Err(format!("timestamp {} is before unix epoch", text)),
+        Err(e) => Err(format!("invalid timestamp {}: {}", text, e)),
+    }
 }